@@ -13,25 +13,102 @@ typedef struct image_weights image_weights;
 /// This is the most expensive part of the process, so save the return to avoid
 /// unnecessarily recomputing it where possible.
 ///
-/// This computation shouldn't be able to fail.
+/// Returns NULL if the pixels don't span all 5 RGBXY dimensions (for example,
+/// if the image is a single solid color).
 image_weights* create_image_weights(uint32_t width, uint32_t height, const uint8_t *rgb_bytes);
+/// Same as `create_image_weights`, but for an image with an alpha channel.
+///
+/// The alpha channel is not part of the decomposition. It is carried along
+/// unchanged and can be recovered with `reconstruct_image_rgba`.
+///
+/// If `exclude_transparent` is non-zero, fully transparent pixels are left out
+/// of the computation entirely and reconstruct as transparent black.
+image_weights* create_image_weights_rgba(
+    uint32_t width,
+    uint32_t height,
+    const uint8_t *rgba_bytes,
+    uint8_t exclude_transparent
+);
 void free_image_weights(image_weights *weights);
 
 /// Compute an initial decomposition palette for an image.
 /// The returned palette is a list of RGB values. The total byte size of the
 /// buffer returned is 3 times the value stored into out_palette_color_count.
 ///
-/// The return palette will never be smaller than 4 colors.
+/// The return palette will never be smaller than 4 colors, unless the colors
+/// of the image all lie on a line or in a triangle (for example, a grayscale
+/// or two-tone image), in which case the palette is the 2 or 3 colors at its
+/// ends or corners.
+///
+/// Returns NULL if the colors of the image all lie on a plane but not in a
+/// triangle, or if the image is a solid color.
 uint8_t *compute_palette(
     uint32_t img_width,
     uint32_t img_heigh,
     const uint8_t *rgb_img_bytes,
     uint8_t min_palette_size,
+    uint8_t max_palette_size,
+    double error_bound,
+    uint8_t *out_palette_color_count
+);
+/// Same as `compute_palette`, but for an image with an alpha channel. Fully
+/// transparent pixels are ignored.
+uint8_t *compute_palette_rgba(
+    uint32_t img_width,
+    uint32_t img_heigh,
+    const uint8_t *rgba_img_bytes,
+    uint8_t min_palette_size,
+    uint8_t max_palette_size,
     double error_bound,
     uint8_t *out_palette_color_count
 );
 void free_computed_palette(uint8_t *palette_bytes, uint8_t palette_color_count);
 
+typedef struct palette_ladder palette_ladder;
+
+/// Compute the decomposition palettes of every size between min_palette_size
+/// and max_palette_size in one pass. This is much faster than calling
+/// `compute_palette` once per size.
+///
+/// The palettes are ordered from largest to smallest. Some sizes may be
+/// skipped, so use `get_palette_ladder_palette` to find the size of each one.
+///
+/// Returns NULL if the colors of the image all lie on a plane (for example, a
+/// solid color or grayscale image).
+palette_ladder *compute_palette_ladder(
+    uint32_t img_width,
+    uint32_t img_heigh,
+    const uint8_t *rgb_img_bytes,
+    uint8_t min_palette_size,
+    uint8_t max_palette_size
+);
+/// Same as `compute_palette_ladder`, but for an image with an alpha channel.
+/// Fully transparent pixels are ignored.
+palette_ladder *compute_palette_ladder_rgba(
+    uint32_t img_width,
+    uint32_t img_heigh,
+    const uint8_t *rgba_img_bytes,
+    uint8_t min_palette_size,
+    uint8_t max_palette_size
+);
+void free_palette_ladder(palette_ladder *ladder);
+
+/// Returns the number of palettes in the ladder.
+uint8_t get_palette_ladder_len(const palette_ladder *ladder);
+/// Returns the palette at `index` as a list of RGB values, and stores the
+/// number of colors into out_palette_color_count. The palette is owned by the
+/// ladder and must not be freed.
+///
+/// Returns NULL if `index` is out of range.
+const uint8_t *get_palette_ladder_palette(
+    const palette_ladder *ladder,
+    uint8_t index,
+    uint8_t *out_palette_color_count
+);
+/// Returns the average per-pixel error of the palette at `index`, or NaN if
+/// `index` is out of range.
+double get_palette_ladder_error(const palette_ladder *ladder, uint8_t index);
+
 
 typedef struct decomposed_image decomposed_image;
 
@@ -39,8 +116,9 @@ typedef struct decomposed_image decomposed_image;
 ///
 /// The provided palette can be created using the `compute_palette` function or
 /// entirely custom. Note that the absolute minimum size for a decomposition
-/// palette is 4 colors. If a smaller palette is provided, the function will
-/// return NULL.
+/// palette is 2 colors. If a smaller palette is provided, the function will
+/// return NULL. A palette of 2 or 3 colors is treated as the segment or
+/// triangle between them, which suits two- and three-tone images.
 ///
 /// If the provided palette contains "redundant" colors, then the function will
 /// return NULL. The decomposition process relies on creating a 3d convex-hull
@@ -78,6 +156,18 @@ uint8_t reconstruct_image(
     uint8_t *output_buf
 );
 
+/// Same as `reconstruct_image`, but the output includes the alpha channel of
+/// the original image. If the original image didn't have an alpha channel,
+/// every pixel is fully opaque.
+///
+/// `output_buf` should contain at least 4 bytes per pixel in the original
+/// image.
+uint8_t reconstruct_image_rgba(
+    const decomposed_image *img,
+    const uint8_t *palette_bytes,
+    uint8_t *output_buf
+);
+
 /// Extract one channel of a decomposed image as grayscale image.
 ///
 /// On success, this function returns 1.
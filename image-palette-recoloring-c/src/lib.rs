@@ -3,8 +3,10 @@ use libc::c_void;
 use std::ptr;
 use std::slice;
 
-use image::{ImageBuffer, Rgb};
-use image_palette_recoloring::{DecomposedImage, ImageWeights};
+use image::{ImageBuffer, Rgb, Rgba};
+use image_palette_recoloring::{
+    DecomposedImage, ImageWeights, ImageWeightsOptions, LadderPalette, PaletteOptions,
+};
 
 // On any platform other than WASM, you should be able to allocate and free a buffer without any
 // extra these methods.
@@ -29,7 +31,34 @@ unsafe extern "C" fn create_image_weights(width: u32, height: u32, bytes: *const
     let Some(img) = ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, bytes_slice) else {
         return ptr::null()
     };
-    let weights = Box::new(ImageWeights::new(&img));
+    let Ok(weights) = ImageWeights::new(&img) else {
+        return ptr::null()
+    };
+    let weights = Box::new(weights);
+
+    Box::into_raw(weights) as *const _
+}
+
+#[no_mangle]
+unsafe extern "C" fn create_image_weights_rgba(
+    width: u32,
+    height: u32,
+    bytes: *const u8,
+    exclude_transparent: u8,
+) -> *const c_void
+{
+    let bytes_slice = slice::from_raw_parts(bytes, 4 * (width * height) as usize);
+    let Some(img) = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, bytes_slice) else {
+        return ptr::null()
+    };
+    let options = ImageWeightsOptions {
+        exclude_transparent: exclude_transparent != 0,
+        ..Default::default()
+    };
+    let Ok(weights) = ImageWeights::with_options(&img, &options) else {
+        return ptr::null()
+    };
+    let weights = Box::new(weights);
 
     Box::into_raw(weights) as *const _
 }
@@ -55,12 +84,43 @@ unsafe extern "C" fn compute_palette(
         return ptr::null_mut()
     };
 
-    let palette = image_palette_recoloring::compute_palette(
+    let Ok(palette) = image_palette_recoloring::compute_palette(
         &img,
         min_palette_size as usize,
         max_palette_size as usize,
         error_bound
-    );
+    ) else {
+        return ptr::null_mut()
+    };
+
+    *out_palette_size = palette.len() as u8;
+    Box::into_raw(palette.into_boxed_slice()) as *mut [u8; 3]
+}
+
+#[no_mangle]
+unsafe extern "C" fn compute_palette_rgba(
+    img_width: u32,
+    img_height: u32,
+    img_bytes: *const u8,
+    min_palette_size: u8,
+    max_palette_size: u8,
+    error_bound: f64,
+    out_palette_size: *mut u8
+) -> *mut [u8; 3]
+{
+    let bytes_slice = slice::from_raw_parts(img_bytes, (img_width * img_height * 4) as usize);
+    let Some(img) = ImageBuffer::<Rgba<u8>, _>::from_raw(img_width, img_height, bytes_slice) else {
+        return ptr::null_mut()
+    };
+
+    let Ok(palette) = image_palette_recoloring::compute_palette(
+        &img,
+        min_palette_size as usize,
+        max_palette_size as usize,
+        error_bound
+    ) else {
+        return ptr::null_mut()
+    };
 
     *out_palette_size = palette.len() as u8;
     Box::into_raw(palette.into_boxed_slice()) as *mut [u8; 3]
@@ -72,6 +132,90 @@ unsafe extern "C" fn free_computed_palette(ptr: *mut [u8; 3], palette_size: u8)
     let _ = Box::from_raw(ptr);
 }
 
+#[no_mangle]
+unsafe extern "C" fn compute_palette_ladder(
+    img_width: u32,
+    img_height: u32,
+    img_bytes: *const u8,
+    min_palette_size: u8,
+    max_palette_size: u8,
+) -> *const c_void
+{
+    let bytes_slice = slice::from_raw_parts(img_bytes, (img_width * img_height * 3) as usize);
+    let Some(img) = ImageBuffer::<Rgb<u8>, _>::from_raw(img_width, img_height, bytes_slice) else {
+        return ptr::null()
+    };
+
+    let options = PaletteOptions {
+        min_palette_size: min_palette_size as usize,
+        max_palette_size: max_palette_size as usize,
+        ..Default::default()
+    };
+    let Ok(ladder) = image_palette_recoloring::compute_palette_ladder(&img, &options) else {
+        return ptr::null()
+    };
+
+    Box::into_raw(Box::new(ladder)) as *const _
+}
+
+#[no_mangle]
+unsafe extern "C" fn compute_palette_ladder_rgba(
+    img_width: u32,
+    img_height: u32,
+    img_bytes: *const u8,
+    min_palette_size: u8,
+    max_palette_size: u8,
+) -> *const c_void
+{
+    let bytes_slice = slice::from_raw_parts(img_bytes, (img_width * img_height * 4) as usize);
+    let Some(img) = ImageBuffer::<Rgba<u8>, _>::from_raw(img_width, img_height, bytes_slice) else {
+        return ptr::null()
+    };
+
+    let options = PaletteOptions {
+        min_palette_size: min_palette_size as usize,
+        max_palette_size: max_palette_size as usize,
+        ..Default::default()
+    };
+    let Ok(ladder) = image_palette_recoloring::compute_palette_ladder(&img, &options) else {
+        return ptr::null()
+    };
+
+    Box::into_raw(Box::new(ladder)) as *const _
+}
+
+#[no_mangle]
+unsafe extern "C" fn free_palette_ladder(ptr: *const c_void) {
+    let _ = Box::from_raw(ptr as *mut c_void as *mut Vec<LadderPalette>);
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_palette_ladder_len(ladder: *const c_void) -> u8 {
+    let ladder = &*(ladder as *const Vec<LadderPalette>);
+    ladder.len() as u8
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_palette_ladder_palette(
+    ladder: *const c_void,
+    index: u8,
+    out_palette_size: *mut u8,
+) -> *const [u8; 3]
+{
+    let ladder = &*(ladder as *const Vec<LadderPalette>);
+    let Some(step) = ladder.get(index as usize) else {
+        return ptr::null()
+    };
+    *out_palette_size = step.palette.len() as u8;
+    step.palette.as_ptr() as *const [u8; 3]
+}
+
+#[no_mangle]
+unsafe extern "C" fn get_palette_ladder_error(ladder: *const c_void, index: u8) -> f64 {
+    let ladder = &*(ladder as *const Vec<LadderPalette>);
+    ladder.get(index as usize).map_or(f64::NAN, |step| step.error)
+}
+
 #[no_mangle]
 unsafe extern "C" fn create_decomposed_image(
     image_weights: *const c_void,
@@ -82,7 +226,7 @@ unsafe extern "C" fn create_decomposed_image(
     let image_weights = &*(image_weights as *const ImageWeights);
     let palette = slice::from_raw_parts(palette as *mut Rgb<u8>, palette_size as usize);
 
-    let decomposed = match DecomposedImage::new(&image_weights, palette) {
+    let decomposed = match DecomposedImage::new(image_weights, palette) {
         Ok(val) => val,
         Err(_) => return ptr::null(),
     };
@@ -118,7 +262,7 @@ unsafe extern "C" fn reconstruct_image(
     let decomposed_image = &*(decomposed_image as *const DecomposedImage);
     let num_channels = decomposed_image.num_channels();
     let palette = slice::from_raw_parts(palette as *mut Rgb<u8>, num_channels);
-    let Some(reconstructed) = decomposed_image.reconstruct(palette) else {
+    let Ok(reconstructed) = decomposed_image.reconstruct(palette) else {
         return 0;
     };
 
@@ -130,6 +274,28 @@ unsafe extern "C" fn reconstruct_image(
     1
 }
 
+#[no_mangle]
+unsafe extern "C" fn reconstruct_image_rgba(
+    decomposed_image: *const c_void,
+    palette: *const [u8; 3],
+    output_buffer: *mut u8,
+) -> u8
+{
+    let decomposed_image = &*(decomposed_image as *const DecomposedImage);
+    let num_channels = decomposed_image.num_channels();
+    let palette = slice::from_raw_parts(palette as *mut Rgb<u8>, num_channels);
+    let Ok(reconstructed) = decomposed_image.reconstruct_rgba(palette) else {
+        return 0;
+    };
+
+    let output_slice = slice::from_raw_parts_mut(
+        output_buffer,
+        (reconstructed.width() * reconstructed.height() * 4) as usize,
+    );
+    output_slice.copy_from_slice(&reconstructed);
+    1
+}
+
 #[no_mangle]
 unsafe extern "C" fn grayscale_image_channel(
     decomposed_image: *const c_void,
@@ -1,11 +1,17 @@
 use image::io::Reader as ImageReader;
-use image::Rgb;
+use image::{ImageBuffer, Rgb};
 use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
-use image_palette_recoloring::{compute_palette, DecomposedImage, ImageWeights};
+use image_palette_recoloring::{
+    compute_palette_ladder, compute_palette_with_options, DecomposedImage, DecompositionOptions,
+    ImageWeights, ImageWeightsOptions, LayerPrecision, PaletteErrorMetric, PaletteOptions,
+    RedundantColorHandling, SourcePixel,
+};
 
 #[derive(Debug, Parser)]
 struct Cli {
@@ -30,6 +36,28 @@ enum Commands {
             default_value_t = 10,
         )]
         max_size: u8,
+        /// Compare the error bound to the actual reconstruction error of each candidate palette
+        /// instead of the distance to its hull. This is slower but more accurate. The error of the
+        /// final palette is printed to stderr.
+        #[arg(long, default_value_t = false)]
+        reconstruction_error: bool,
+        /// Print the palette of every size from max-size down to min-size, one per line, largest
+        /// first. The error bound is ignored and the error of each palette is printed to stderr.
+        #[arg(long, default_value_t = false)]
+        all_sizes: bool,
+        /// Colors that must be in the palette, eg. `ff0000,ffffff`. These are never simplified
+        /// away.
+        #[arg(long, value_parser = clap::builder::ValueParser::new(parse_color_list))]
+        lock: Option<ColorList>,
+        /// Keep every palette color inside the RGB cube while simplifying, instead of clamping the
+        /// colors at the end. This avoids the error clamping causes, but may give a larger palette.
+        /// Only supported in the srgb and linear-srgb color spaces.
+        #[arg(long, default_value_t = false)]
+        constrain_to_gamut: bool,
+        /// The color space to simplify the palette in. Use the same color space when recoloring
+        /// with the palette.
+        #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+        color_space: ColorSpace,
         #[arg(value_name = "INPUT_IMAGE")]
         input_image: PathBuf,
     },
@@ -44,6 +72,77 @@ enum Commands {
         output_image: PathBuf,
         #[arg(short = 'c', long, default_value_t = false)]
         save_individual_channels: bool,
+        /// File used to cache the image weights between runs. The weights are loaded from here if
+        /// they match the input image, otherwise they are recomputed and written back.
+        #[arg(short, long)]
+        weights_cache: Option<PathBuf>,
+        /// Save the decomposition so it can be recolored later with `reconstruct-image`.
+        #[arg(short, long)]
+        save_decomposition: Option<PathBuf>,
+        /// What to do with decomposition palette colors that are inside the convex hull of the
+        /// other colors.
+        #[arg(long, value_enum, default_value_t = RedundantColors::Error)]
+        redundant_colors: RedundantColors,
+        /// The color space to decompose and reconstruct the image in. Cached weights computed in
+        /// a different color space are recomputed.
+        #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+        color_space: ColorSpace,
+        /// How much the position of a pixel counts relative to its color. Larger values give more
+        /// spatially coherent layers, smaller values give sparser layers. Cached weights computed
+        /// with a different spatial weight are recomputed.
+        #[arg(long, default_value_t = 1.0)]
+        spatial_weight: f64,
+    },
+    ReconstructImage {
+        #[arg(short, long)]
+        decomposition: PathBuf,
+        #[arg(short, long, value_parser = clap::builder::ValueParser::new(parse_color_list))]
+        reconstruction_palette: ColorList,
+        #[arg(short, long)]
+        output_image: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RedundantColors {
+    /// Fail with an error.
+    Error,
+    /// Give the redundant colors empty channels.
+    Drop,
+    /// Give the redundant colors channels of their own.
+    Triangulate,
+}
+
+impl From<RedundantColors> for RedundantColorHandling {
+    fn from(value: RedundantColors) -> Self {
+        match value {
+            RedundantColors::Error => RedundantColorHandling::Error,
+            RedundantColors::Drop => RedundantColorHandling::Drop,
+            RedundantColors::Triangulate => RedundantColorHandling::Triangulate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColorSpace {
+    /// Gamma-encoded sRGB.
+    Srgb,
+    /// Linear sRGB.
+    LinearSrgb,
+    /// OKLab.
+    Oklab,
+    /// CIELAB.
+    Lab,
+}
+
+impl From<ColorSpace> for image_palette_recoloring::ColorSpace {
+    fn from(value: ColorSpace) -> Self {
+        match value {
+            ColorSpace::Srgb => image_palette_recoloring::ColorSpace::Srgb,
+            ColorSpace::LinearSrgb => image_palette_recoloring::ColorSpace::LinearSrgb,
+            ColorSpace::Oklab => image_palette_recoloring::ColorSpace::Oklab,
+            ColorSpace::Lab => image_palette_recoloring::ColorSpace::Lab,
+        }
     }
 }
 
@@ -64,7 +163,7 @@ fn parse_color_list(list: &str) -> Result<ColorList, String> {
             Err(format!("Color {i} isn't valid: wrong length."))?
         }
         if !color_str.is_ascii() {
-            Err(format!("Color list contains non-ascii characters."))?
+            Err("Color list contains non-ascii characters.".to_string())?
         }
         colors.push(Rgb([
             u8::from_str_radix(&color_str[..2], 16)
@@ -78,18 +177,97 @@ fn parse_color_list(list: &str) -> Result<ColorList, String> {
     Ok(ColorList(colors))
 }
 
+fn format_palette(palette: &[Rgb<u8>]) -> String {
+    let palette_hex: Vec<String> = palette.iter()
+        .map(|color| format!("{:02x}{:02x}{:02x}", color.0[0], color.0[1], color.0[2]))
+        .collect();
+    palette_hex.join(",")
+}
+
+fn load_or_compute_weights<P>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    options: &ImageWeightsOptions,
+    cache_path: Option<&PathBuf>,
+) -> Result<ImageWeights, Box<dyn std::error::Error>>
+    where P: SourcePixel + Sync,
+          P::Subpixel: Sync,
+{
+    let Some(cache_path) = cache_path else {
+        return Ok(ImageWeights::with_options(img, options)?)
+    };
+    if let Ok(file) = File::open(cache_path) {
+        if let Ok(weights) = ImageWeights::load_for_image(&mut BufReader::new(file), img) {
+            if weights.color_space() == options.color_space
+                && weights.spatial_weight() == options.spatial_weight
+            {
+                return Ok(weights)
+            }
+        }
+    }
+    let weights = ImageWeights::with_options(img, options)?;
+    let mut writer = BufWriter::new(File::create(cache_path)?);
+    weights.save(&mut writer, img)?;
+    writer.into_inner()?;
+    Ok(weights)
+}
+
+fn save_reconstruction(
+    decomposed: &DecomposedImage,
+    palette: &[Rgb<u8>],
+    output_image: &PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if decomposed.has_alpha() {
+        decomposed.reconstruct_rgba(palette)?.save(output_image)?;
+    } else {
+        decomposed.reconstruct(palette)?.save(output_image)?;
+    }
+    Ok(())
+}
+
 fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
     match cli.commands {
-        Commands::GeneratePalette { error_bound, min_size, max_size, input_image } => {
+        Commands::GeneratePalette {
+            error_bound,
+            min_size,
+            max_size,
+            reconstruction_error,
+            all_sizes,
+            lock,
+            constrain_to_gamut,
+            color_space,
+            input_image,
+        } => {
             let img = ImageReader::open(&input_image)
                 .unwrap().decode().unwrap();
-            let img = img.into_rgb8();
-            let palette = compute_palette(&img, min_size as usize, max_size as usize, error_bound);
-            let palette_hex: Vec<String> = palette.iter()
-                .map(|color| format!("{:02x}{:02x}{:02x}", color.0[0], color.0[1], color.0[2]))
-                .collect();
-            println!("{}", palette_hex.join(","));
+            // Fully transparent pixels are ignored by compute_palette, so there's no harm in always
+            // including the alpha channel.
+            let img = img.into_rgba8();
+            let options = PaletteOptions {
+                min_palette_size: min_size as usize,
+                max_palette_size: max_size as usize,
+                error_bound,
+                error_metric: if reconstruction_error {
+                    PaletteErrorMetric::Reconstruction
+                } else {
+                    PaletteErrorMetric::HullDistance
+                },
+                locked_colors: lock.map(|colors| colors.0).unwrap_or_default(),
+                constrain_to_gamut,
+                color_space: color_space.into(),
+            };
+            if all_sizes {
+                for step in compute_palette_ladder(&img, &options)? {
+                    eprintln!("{} colors: error {}", step.palette.len(), step.error);
+                    println!("{}", format_palette(&step.palette));
+                }
+            } else {
+                let report = compute_palette_with_options(&img, &options)?;
+                if reconstruction_error {
+                    eprintln!("Reconstruction error: {}", report.error);
+                }
+                println!("{}", format_palette(&report.palette));
+            }
         },
         Commands::RecolorImage {
             decomposition_palette,
@@ -97,17 +275,44 @@ fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
             reconstruction_palette,
             output_image,
             save_individual_channels,
+            weights_cache,
+            save_decomposition,
+            redundant_colors,
+            color_space,
+            spatial_weight,
         } => {
             if decomposition_palette.len() != reconstruction_palette.len() {
                 panic!("The decomposition_palette and reconstruction_palette must be the same size.")
             }
             let img = ImageReader::open(&input_image).unwrap().decode().unwrap();
-            let img = img.into_rgb8();
-            let weights = ImageWeights::new(&img);
-            let decomposed = DecomposedImage::new(&weights, &decomposition_palette).unwrap();
+            let weights_options = ImageWeightsOptions {
+                color_space: color_space.into(),
+                spatial_weight,
+                ..Default::default()
+            };
+            let weights = if img.color().has_alpha() {
+                let img = img.into_rgba8();
+                load_or_compute_weights(&img, &weights_options, weights_cache.as_ref())?
+            } else {
+                let img = img.into_rgb8();
+                load_or_compute_weights(&img, &weights_options, weights_cache.as_ref())?
+            };
+            let options = DecompositionOptions {
+                redundant_colors: redundant_colors.into(),
+                ..Default::default()
+            };
+            let decomposed = DecomposedImage::with_options(
+                &weights,
+                &decomposition_palette,
+                &options,
+            )?;
+            if let Some(path) = save_decomposition {
+                let mut writer = BufWriter::new(File::create(path)?);
+                decomposed.save(&mut writer, LayerPrecision::F32)?;
+                writer.into_inner()?;
+            }
 
-            let reconstructed_img = decomposed.reconstruct(&reconstruction_palette).unwrap();
-            reconstructed_img.save(&output_image).unwrap();
+            save_reconstruction(&decomposed, &reconstruction_palette, &output_image)?;
 
             if save_individual_channels {
                 let filename_stem = output_image.file_stem().unwrap();
@@ -124,6 +329,11 @@ fn main_inner() -> Result<(), Box<dyn std::error::Error>> {
                     channel_img.save(dir.join(filename)).unwrap();
                 }
             }
+        },
+        Commands::ReconstructImage { decomposition, reconstruction_palette, output_image } => {
+            let mut reader = BufReader::new(File::open(decomposition)?);
+            let decomposed = DecomposedImage::load(&mut reader)?;
+            save_reconstruction(&decomposed, &reconstruction_palette, &output_image)?;
         }
     }
     Ok(())
@@ -44,19 +44,15 @@ class RecoloringThreadWorker extends ThreadWorker {
         let { method, args } = payload;
         if (method == "createImageWeights") {
             let [array, width, height] = args;
-            let bufPtr = wasmInst.exports.create_image_buffer(width * height * 3);
-            let buf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr, width * height * 3);
+            let bufPtr = wasmInst.exports.create_image_buffer(width * height * 4);
+            let buf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr, width * height * 4);
             console.log(`bufPtr: ${bufPtr}`);
 
-            for (let i = 0; i < width * height; i++) {
-                buf[i * 3] = array[i * 4];
-                buf[i * 3 + 1] = array[i * 4 + 1];
-                buf[i * 3 + 2] = array[i * 4 + 2];
-            }
+            buf.set(array);
 
             let weightsPtr;
             try {
-                weightsPtr =  wasmInst.exports.create_image_weights(width, height, bufPtr);
+                weightsPtr =  wasmInst.exports.create_image_weights_rgba(width, height, bufPtr, 0);
             } catch (e) {
                 let decoder = new TextDecoder();
                 console.log("stdout: ", decoder.decode(stdout.data));
@@ -64,34 +60,33 @@ class RecoloringThreadWorker extends ThreadWorker {
                 throw e;
             } finally {
                 // We don't want to leak wasm memory :(
-                wasmInst.exports.free_image_buffer(width * height * 3, bufPtr);
+                wasmInst.exports.free_image_buffer(width * height * 4, bufPtr);
+            }
+            if (weightsPtr == 0) {
+                throw new Error("The image doesn't have enough distinct colors to be recolored.");
             }
             this.sendResponse(id, [weightsPtr, array], [array.buffer]);
         } else if (method == "computePalette") {
             let [array, width, height, minPaletteSize, maxPaletteSize, errorBound] = args;
-            let bufPtr = wasmInst.exports.create_image_buffer(width * height * 3 + 1);
-            let buf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr, width * height * 3 + 1);
-            for (let i = 0; i < width * height; i++) {
-                buf[i * 3] = array[i * 4];
-                buf[i * 3 + 1] = array[i * 4 + 1];
-                buf[i * 3 + 2] = array[i * 4 + 2];
-            }
-            buf[width * height * 3] = 255;
+            let bufPtr = wasmInst.exports.create_image_buffer(width * height * 4 + 1);
+            let buf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr, width * height * 4 + 1);
+            buf.set(array);
+            buf[width * height * 4] = 255;
 
             let palettePtr;
 
             let paletteSize;
             try {
-                palettePtr = wasmInst.exports.compute_palette(
+                palettePtr = wasmInst.exports.compute_palette_rgba(
                     width,
                     height,
                     bufPtr,
                     minPaletteSize,
                     maxPaletteSize,
                     errorBound,
-                    bufPtr + width * height * 3,
+                    bufPtr + width * height * 4,
                 );
-                let paletteSizeBuf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr + width * height * 3, 1);
+                let paletteSizeBuf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, bufPtr + width * height * 4, 1);
                 paletteSize = paletteSizeBuf[0];
             } catch (e) {
                 let decoder = new TextDecoder();
@@ -99,7 +94,10 @@ class RecoloringThreadWorker extends ThreadWorker {
                 console.log("stderr: ", decoder.decode(stderr.data));
                 throw e;
             } finally {
-                wasmInst.exports.free_image_buffer(width * height * 3 + 1, bufPtr);
+                wasmInst.exports.free_image_buffer(width * height * 4 + 1, bufPtr);
+            }
+            if (palettePtr == 0) {
+                throw new Error("The image doesn't have enough distinct colors to compute a palette.");
             }
 
             let paletteBuf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, palettePtr, 3 * paletteSize);
@@ -156,29 +154,22 @@ class RecoloringThreadWorker extends ThreadWorker {
             }
             let width = wasmInst.exports.get_decomposed_image_width(decompImagePtr);
             let height = wasmInst.exports.get_decomposed_image_height(decompImagePtr);
-            let imagePtr = wasmInst.exports.create_image_buffer(width * height * 3);
+            let imagePtr = wasmInst.exports.create_image_buffer(width * height * 4);
 
             let success;
             try {
-                success = wasmInst.exports.reconstruct_image(decompImagePtr, palettePtr, imagePtr);
+                success = wasmInst.exports.reconstruct_image_rgba(decompImagePtr, palettePtr, imagePtr);
             } catch (e) {
-                wasmInst.exports.free_image_buffer(width * height * 3, imagePtr);
+                wasmInst.exports.free_image_buffer(width * height * 4, imagePtr);
                 throw e;
             } finally {
                 wasmInst.exports.free_image_buffer(paletteColors.length * 3, palettePtr);
             }
 
             if (success) {
-                let imageBuf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, imagePtr, width * height * 3);
-                let outputArray = new Uint8ClampedArray(width * height * 4);
-                for (let i = 0; i < width * height; i++) {
-                    outputArray[i * 4] = imageBuf[i * 3];
-                    outputArray[i * 4 + 1] = imageBuf[i * 3 + 1];
-                    outputArray[i * 4 + 2] = imageBuf[i * 3 + 2];
-                    // 100% alpha
-                    outputArray[i * 4 + 3] = 255;
-                }
-                wasmInst.exports.free_image_buffer(width * height * 3, imagePtr);
+                let imageBuf = new Uint8ClampedArray(wasmInst.exports.memory.buffer, imagePtr, width * height * 4);
+                let outputArray = new Uint8ClampedArray(imageBuf);
+                wasmInst.exports.free_image_buffer(width * height * 4, imagePtr);
                 let blob = await createBlobForArray(outputArray, width, height);
                 this.sendResponse(id, blob);
             } else {
@@ -0,0 +1,46 @@
+// Compare against the previous simplification by saving a baseline before the change:
+//     cargo bench --bench palette -- --save-baseline before
+// and then running with `--baseline before` after it.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use image::io::Reader as ImageReader;
+use image::{ImageBuffer, Rgb};
+use image_palette_recoloring::compute_palette;
+
+/// An image whose colors are spread over a sphere in RGB space, so nearly every color is a vertex
+/// of the hull.
+fn sphere_image(size: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let count = (size * size) as f64;
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    ImageBuffer::from_fn(size, size, |x, y| {
+        let i = (x + y * size) as f64;
+        let z = 1.0 - 2.0 * (i + 0.5) / count;
+        let radius = (1.0 - z * z).sqrt();
+        let theta = golden_angle * i;
+        let to_u8 = |c: f64| (127.5 + 127.0 * c).round() as u8;
+        Rgb([to_u8(radius * theta.cos()), to_u8(radius * theta.sin()), to_u8(z)])
+    })
+}
+
+fn simplify_large_hull(c: &mut Criterion) {
+    let img = sphere_image(16);
+    c.bench_function(
+        "simplify_sphere_256",
+        |b| b.iter(|| compute_palette(&img, 4, 10, f64::INFINITY).unwrap()),
+    );
+
+    let img = ImageReader::open("../qhull-rs/benches/test_image.png").unwrap().decode().unwrap();
+    let img = img.into_rgb8();
+    c.bench_function(
+        "simplify_test_image",
+        |b| b.iter(|| compute_palette(&img, 4, 10, f64::INFINITY).unwrap()),
+    );
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = simplify_large_hull
+}
+criterion_main!(benches);
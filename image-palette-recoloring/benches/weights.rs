@@ -0,0 +1,40 @@
+// Compare against a previous version by saving a baseline before the change:
+//     cargo bench --bench weights -- --save-baseline before
+// and then running with `--baseline before` after it.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use image::io::Reader as ImageReader;
+use image::{ImageBuffer, Rgb};
+use image_palette_recoloring::ImageWeights;
+
+/// An image made of large blocks of solid color, like UI art or a vector render. Nearly every pixel
+/// shares its color with its neighbors.
+fn flat_image(size: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(size, size, |x, y| {
+        let (bx, by) = (x * 6 / size, y * 6 / size);
+        Rgb([(bx * 51) as u8, (by * 51) as u8, ((bx * by * 37) % 256) as u8])
+    })
+}
+
+fn image_weights(c: &mut Criterion) {
+    let img = flat_image(512);
+    c.bench_function(
+        "weights_flat_512",
+        |b| b.iter(|| ImageWeights::new(&img).unwrap()),
+    );
+
+    let img = ImageReader::open("../qhull-rs/benches/test_image.png").unwrap().decode().unwrap();
+    let img = img.into_rgb8();
+    c.bench_function(
+        "weights_test_image",
+        |b| b.iter(|| ImageWeights::new(&img).unwrap()),
+    );
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = image_weights
+}
+criterion_main!(benches);
@@ -0,0 +1,301 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use good_lp::{ProblemVariables, VariableDefinition, SolverModel};
+use good_lp::solvers::Solution;
+use nalgebra::{Const, Vector3};
+use qhull_rs::ConvexHull;
+use qhull_rs::convex_hull::{Vertex, Facet};
+
+/// A point keyed by the bit patterns of its coordinates. The hull is rebuilt after every collapse,
+/// which renumbers its vertices and facets, so the coordinates are the only stable way to identify
+/// them from one iteration to the next.
+type PointKey = [u64; 3];
+
+fn point_key(p: &Vector3<f64>) -> PointKey {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+// We're going to simply the convex hull/palette by colapsing edges. It isn't easy to extract edge
+// data from qhull, so we'll compute it here in a slightly crude way.
+struct EdgeData<'a> {
+    edges: Box<[Edge<'a>]>,
+}
+
+// The two ends of an edge and the facets that share it.
+type Edge<'a> = ([Vertex<'a, Const<3>>; 2], Box<[Facet<'a, Const<3>>]>);
+
+impl<'a> EdgeData<'a> {
+    fn new(ch: &'a ConvexHull<Const<3>>) -> Self {
+        // TODO: Key these maps on something other than the vertices and facets themselves.
+        let mut faces_for_vertex = HashMap::new();
+        let mut edges = HashMap::new();
+        for facet in ch.facets() {
+            let mut it = facet.vertices();
+            let a = it.next().unwrap();
+            let b = it.next().unwrap();
+            let c = it.next().unwrap();
+            for v in &[a, b, c] {
+                faces_for_vertex.entry(*v)
+                    .or_insert(vec![])
+                    .push(facet)
+            }
+            for (start, end) in &[(a, b), (b, c), (c, a)] {
+                let (start, end) = if start.index() > end.index() {
+                    (end, start)
+                } else {
+                    (start, end)
+                };
+                edges.entry([*start, *end])
+                    .or_insert(vec![]);
+                // faces.push(facet)
+            }
+        }
+        for ([start, end], faces) in edges.iter_mut() {
+            let start_faces = &faces_for_vertex[start];
+            let end_faces = &faces_for_vertex[end];
+
+            // The 2 points should have only 2 faces in common.
+            faces.reserve(start_faces.len() + end_faces.len());
+            faces.extend(start_faces);
+            faces.extend(end_faces);
+            faces.sort_by_key(|f: &Facet<Const<3>>| f.index());
+            faces.dedup_by_key(|f| f.index());
+        }
+        let mut edges = edges.into_iter()
+            .map(|(key, value)| (key, value.into_boxed_slice()))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        edges.sort_by_key(|(verts, _)| (verts[0].index(), verts[1].index()));
+        EdgeData {
+            edges,
+        }
+    }
+
+    fn iter<'s>(&'s self)
+        -> impl ExactSizeIterator<Item = ([Vertex<'a, Const<3>>; 2], &'s [Facet<'a, Const<3>>])>
+    {
+        self.edges
+            .iter()
+            .map(|(vs, fs)| (*vs, &fs[..]))
+    }
+}
+
+
+/// The LP inputs of an edge the last time its collapse was solved.
+struct EdgeState {
+    // The faces incident to either end of the edge. Each face starts with its smallest vertex,
+    // keeping the orientation, and the faces are sorted so the order qhull lists them in doesn't
+    // matter.
+    faces: Box<[[PointKey; 3]]>,
+    generation: u64,
+}
+
+/// A possible edge collapse, ordered by the volume it adds to the hull.
+struct QueueEntry {
+    volume: f64,
+    edge: [PointKey; 2],
+    new_point: [f64; 3],
+    generation: u64,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry { }
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Ties are broken by the edge so that the result doesn't depend on the order the edges
+        // were visited in.
+        self.volume.total_cmp(&other.volume)
+            .then_with(|| self.edge.cmp(&other.edge))
+            .then_with(|| self.generation.cmp(&other.generation))
+    }
+}
+
+/// Picks the edges to collapse while simplifying a palette's hull.
+///
+/// Finding the best point to collapse an edge to takes solving an LP, which is by far the most
+/// expensive part of the simplification. Collapsing an edge only changes the hull around that
+/// edge, so the LP of every other edge has the same inputs as before. This keeps the result of
+/// every edge's LP along with its inputs, and only solves it again if the faces around the edge
+/// have changed. The results are kept in a priority queue ordered by the added volume. Entries for
+/// edges that have since changed or disappeared are discarded when they reach the front.
+pub(crate) struct EdgeCollapser {
+    edges: HashMap<[PointKey; 2], EdgeState>,
+    queue: BinaryHeap<Reverse<QueueEntry>>,
+    generation: u64,
+    locked: HashSet<PointKey>,
+    constrain_to_gamut: bool,
+}
+
+impl EdgeCollapser {
+    /// Edges with a vertex at one of the `locked` points are never collapsed. If
+    /// `constrain_to_gamut` is set, edges are only collapsed to points inside the RGB cube.
+    pub(crate) fn new(locked: &[Vector3<f64>], constrain_to_gamut: bool) -> Self {
+        EdgeCollapser {
+            edges: HashMap::new(),
+            queue: BinaryHeap::new(),
+            generation: 0,
+            locked: locked.iter().map(point_key).collect(),
+            constrain_to_gamut,
+        }
+    }
+
+    /// Skip the collapse last returned by `locate_edge_to_collapse`. The edge won't be considered
+    /// again unless the faces around it change.
+    pub(crate) fn reject_last(&mut self) {
+        self.queue.pop();
+    }
+
+    /// Find the edge of `ch` whose collapse adds the least volume. Returns the point to collapse it
+    /// to and the indices of the edge's vertices.
+    ///
+    /// `ch` should be the hull resulting from the previous collapse, so that only the edges around
+    /// it need to be solved again.
+    ///
+    /// Returns `None` if no edge can be collapsed.
+    pub(crate) fn locate_edge_to_collapse(&mut self, ch: &ConvexHull<Const<3>>)
+        -> Option<([f64; 3], [usize; 2])>
+    {
+        let vertex_indices: HashMap<PointKey, usize> = ch.vertices()
+            .map(|v| (point_key(v.point()), v.index()))
+            .collect();
+
+        let edge_data = EdgeData::new(ch);
+        let mut edges = HashMap::with_capacity(edge_data.edges.len());
+        for (edge, faces) in edge_data.iter() {
+            let mut key = [point_key(edge[0].point()), point_key(edge[1].point())];
+            key.sort();
+            if key.iter().any(|point| self.locked.contains(point)) {
+                continue
+            }
+
+            let face_points: Vec<[Vector3<f64>; 3]> = faces.iter()
+                .map(|face| {
+                    let mut verts = face.vertices();
+                    let p0 = *verts.next().unwrap().point();
+                    let p1 = *verts.next().unwrap().point();
+                    let p2 = *verts.next().unwrap().point();
+                    [p0, p1, p2]
+                })
+                .collect();
+            let mut face_keys: Box<[[PointKey; 3]]> = face_points.iter()
+                .map(|points| {
+                    let mut face = [
+                        point_key(&points[0]), point_key(&points[1]), point_key(&points[2]),
+                    ];
+                    let first = (0..3).min_by_key(|&i| face[i]).unwrap();
+                    face.rotate_left(first);
+                    face
+                })
+                .collect();
+            face_keys.sort();
+
+            match self.edges.remove(&key) {
+                Some(state) if state.faces == face_keys => {
+                    // Nothing around this edge has changed, so its queue entry is still valid.
+                    edges.insert(key, state);
+                },
+                _ => {
+                    self.generation += 1;
+                    let solution = solve_edge_collapse(&face_points, self.constrain_to_gamut);
+                    if let Some((new_point, volume)) = solution {
+                        self.queue.push(Reverse(QueueEntry {
+                            volume,
+                            edge: key,
+                            new_point,
+                            generation: self.generation,
+                        }));
+                    }
+                    edges.insert(key, EdgeState { faces: face_keys, generation: self.generation });
+                },
+            }
+        }
+        self.edges = edges;
+
+        while let Some(Reverse(entry)) = self.queue.peek() {
+            let is_current = self.edges.get(&entry.edge)
+                .is_some_and(|state| state.generation == entry.generation);
+            if is_current {
+                let [start, end] = entry.edge;
+                return Some((entry.new_point, [vertex_indices[&start], vertex_indices[&end]]))
+            }
+            self.queue.pop();
+        }
+        None
+    }
+}
+
+/// Find the point to collapse an edge to, given the faces incident to either end of the edge.
+/// Returns the point and the volume collapsing the edge to it adds to the hull, or `None` if there
+/// is no such point (inside the RGB cube, if `constrain_to_gamut` is set).
+fn solve_edge_collapse(faces: &[[Vector3<f64>; 3]], constrain_to_gamut: bool)
+    -> Option<([f64; 3], f64)>
+{
+    // Look at the all of the faces to which these 2 vertices are incident. We want to locate a
+    // point that will result in positive volume for all of the tetrahedrons formed by each face
+    // and the new point. This will ensure that we strictly increase volume when we collapse the
+    // edge to this point.
+    let mut a = vec![];
+    let mut b = vec![];
+    let mut c = Vector3::new(0.0, 0.0, 0.0);
+    for [p0, p1, p2] in faces {
+        // XXX This only works because we ensure a consistent ordering of the vertices of the
+        //     facets 3d hulls relative to their normals. QHull does not natively do that for
+        //     us :\
+        let mut n = (p1 - p0).cross(&(p2 - p0));
+        n.normalize_mut();
+
+        a.push(n);
+        b.push(n.dot(p0));
+        c += n;
+    }
+    for v in &mut a {
+        *v *= -1.0;
+    }
+    for v in &mut b {
+        *v *= -1.0;
+    }
+
+    let variable = |name| {
+        let definition = VariableDefinition::new().name(name);
+        if constrain_to_gamut {
+            definition.min(0.0).max(1.0)
+        } else {
+            definition
+        }
+    };
+    let mut vars = ProblemVariables::new();
+    let px = vars.add(variable("px"));
+    let py = vars.add(variable("py"));
+    let pz = vars.add(variable("pz"));
+    let mut model = vars.minimise(px * c[0] + py * c[1] + pz * c[2])
+        .using(good_lp::default_solver);
+
+    for (coefs, bound) in a.iter().zip(&b) {
+        model.add_constraint((px * coefs[0] + py * coefs[1] + pz * coefs[2]).leq(*bound));
+    }
+    let solution = model.solve().ok()?;
+    let p = [solution.value(px), solution.value(py), solution.value(pz)];
+    let vol = faces.iter()
+        .map(|[p0, p1, p2]| tetrahedron_volume(p0, p1, p2, &p.into()))
+        .sum::<f64>();
+    Some((p, vol))
+}
+
+fn tetrahedron_volume(a: &Vector3<f64>, b: &Vector3<f64>, c: &Vector3<f64>, d: &Vector3<f64>)
+    -> f64
+{
+    (a - d).dot(&(b - d).cross(&(c - d))).abs() / 6.0
+}
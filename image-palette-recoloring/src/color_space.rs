@@ -0,0 +1,159 @@
+/// The color space the convex hulls, the decomposition and the reconstruction are computed in.
+///
+/// Pixels and palettes are always given and returned as gamma-encoded sRGB. They are converted to
+/// the color space on the way in and converted back on the way out. Every color space is scaled so
+/// that its lightness runs from 0 to 1, which keeps the color dimensions of the RGBXY hull on the
+/// same scale as the position dimensions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Gamma-encoded sRGB. Colors are used exactly as they are given.
+    #[default]
+    Srgb,
+    /// sRGB with the gamma removed. Blending layers in linear light avoids the muddy mid-tones
+    /// that blending gamma-encoded colors gives.
+    LinearSrgb,
+    /// The OKLab perceptual color space.
+    Oklab,
+    /// The CIELAB perceptual color space, relative to a D65 white point, with every component
+    /// divided by 100.
+    Lab,
+}
+
+impl ColorSpace {
+    /// Convert a gamma-encoded sRGB color with components in the range 0-1 to this color space.
+    ///
+    /// Colors outside of the range 0-1 are converted as well, so they survive the round trip
+    /// through `to_srgb`.
+    pub fn from_srgb(self, rgb: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => rgb,
+            ColorSpace::LinearSrgb => rgb.map(srgb_to_linear),
+            ColorSpace::Oklab => linear_to_oklab(rgb.map(srgb_to_linear)),
+            ColorSpace::Lab => linear_to_lab(rgb.map(srgb_to_linear)),
+        }
+    }
+
+    /// Convert a color in this color space back to gamma-encoded sRGB.
+    ///
+    /// The result isn't clamped, so colors outside of the sRGB gamut have components outside of
+    /// the range 0-1.
+    pub fn to_srgb(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => color,
+            ColorSpace::LinearSrgb => color.map(linear_to_srgb),
+            ColorSpace::Oklab => oklab_to_linear(color).map(linear_to_srgb),
+            ColorSpace::Lab => lab_to_linear(color).map(linear_to_srgb),
+        }
+    }
+
+    /// Returns `true` if the sRGB gamut is the unit cube in this color space.
+    pub(crate) fn gamut_is_cube(self) -> bool {
+        matches!(self, ColorSpace::Srgb | ColorSpace::LinearSrgb)
+    }
+
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            ColorSpace::Srgb => 0,
+            ColorSpace::LinearSrgb => 1,
+            ColorSpace::Oklab => 2,
+            ColorSpace::Lab => 3,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(ColorSpace::Srgb),
+            1 => Some(ColorSpace::LinearSrgb),
+            2 => Some(ColorSpace::Oklab),
+            3 => Some(ColorSpace::Lab),
+            _ => None,
+        }
+    }
+}
+
+// The transfer functions are mirrored for negative values so that they are invertible everywhere.
+fn srgb_to_linear(c: f64) -> f64 {
+    let a = c.abs();
+    let linear = if a <= 0.04045 { a / 12.92 } else { ((a + 0.055) / 1.055).powf(2.4) };
+    linear.copysign(c)
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    let a = c.abs();
+    let srgb = if a <= 0.0031308 { a * 12.92 } else { 1.055 * a.powf(1.0 / 2.4) - 0.055 };
+    srgb.copysign(c)
+}
+
+fn linear_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+fn oklab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+}
+
+// The D65 white point, which sRGB's white maps to.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+const DELTA: f64 = 6.0 / 29.0;
+
+fn linear_to_lab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let xyz = [
+        0.4124564 * r + 0.3575761 * g + 0.1804375 * b,
+        0.2126729 * r + 0.7151522 * g + 0.0721750 * b,
+        0.0193339 * r + 0.1191920 * g + 0.9503041 * b,
+    ];
+    let f = |t: f64| if t > DELTA.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    };
+    let [fx, fy, fz] = [f(xyz[0] / WHITE[0]), f(xyz[1] / WHITE[1]), f(xyz[2] / WHITE[2])];
+    [(116.0 * fy - 16.0) / 100.0, 5.0 * (fx - fy), 2.0 * (fy - fz)]
+}
+
+// The exact inverse of the matrix in `linear_to_lab`, so the round trip is lossless near black.
+fn lab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let fy = (l * 100.0 + 16.0) / 116.0;
+    let fx = fy + a / 5.0;
+    let fz = fy - b / 2.0;
+    let f_inv = |t: f64| if t > DELTA { t.powi(3) } else { 3.0 * DELTA * DELTA * (t - 4.0 / 29.0) };
+    let [x, y, z] = [f_inv(fx) * WHITE[0], f_inv(fy) * WHITE[1], f_inv(fz) * WHITE[2]];
+    [
+        3.2404548360 * x - 1.5371388501 * y - 0.4985315469 * z,
+        -0.9692663899 * x + 1.8760109288 * y + 0.0415560823 * z,
+        0.0556434196 * x - 0.2040258543 * y + 1.0572251625 * z,
+    ]
+}
+
+#[test]
+fn test_round_trip() {
+    let colors = [
+        [0.0, 0.0, 0.0], [1.0, 1.0, 1.0], [1.0, 0.0, 0.0], [0.2, 0.5, 0.9], [0.01, 0.02, 0.0],
+        [-0.1, 0.5, 1.2],
+    ];
+    for space in [ColorSpace::Srgb, ColorSpace::LinearSrgb, ColorSpace::Oklab, ColorSpace::Lab] {
+        for color in colors {
+            let round_trip = space.to_srgb(space.from_srgb(color));
+            for c in 0..3 {
+                assert!((round_trip[c] - color[c]).abs() < 1e-6, "{space:?} {color:?}");
+            }
+        }
+        // Lightness runs from 0 to 1.
+        assert!(space.from_srgb([0.0; 3])[0].abs() < 1e-6);
+        assert!((space.from_srgb([1.0; 3])[0] - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,62 @@
+/// Errors that can occur while decomposing or recoloring an image.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum RecolorError {
+    /// The palette has fewer colors than the minimum palette size.
+    #[error("The minimum palette size is {min}. Only {actual} colors were provided.")]
+    PaletteTooSmall { min: usize, actual: usize },
+
+    /// Not every color of the palette is a vertex of the palette's 3D convex hull.
+    #[error(
+        "The palette contains redundant colors (not all colors are present in the 3d convex hull \
+        of the palette)"
+    )]
+    RedundantColors,
+
+    /// A reconstruction palette doesn't have the same number of colors as the decomposition
+    /// palette.
+    #[error("Expected a palette of {expected} colors, but {actual} were provided.")]
+    PaletteSizeMismatch { expected: usize, actual: usize },
+
+    /// The points don't span enough dimensions to form a convex hull. This happens, for example,
+    /// with a solid-color image or a palette whose colors all lie on a plane.
+    #[error("The {0} are degenerate: they don't span enough dimensions to form a convex hull.")]
+    DegenerateHull(&'static str),
+
+    /// A locked palette color is inside the convex hull of the image's colors, so it can't be a
+    /// color of a decomposition palette.
+    #[error("The locked color {0:?} is inside the convex hull of the image's colors.")]
+    LockedColorInsideHull([u8; 3]),
+
+    /// The star vertex chosen for a decomposition isn't one of the palette's colors, or isn't a
+    /// vertex of the palette's convex hull.
+    #[error("Palette color {0} can't be the star vertex, it isn't a vertex of the palette's hull.")]
+    InvalidStarVertex(usize),
+
+    /// `PaletteOptions::constrain_to_gamut` was set for a color space in which the sRGB gamut
+    /// isn't a cube.
+    #[error("Palette colors can't be constrained to the gamut in the {0:?} color space.")]
+    GamutConstraintUnsupported(crate::ColorSpace),
+
+    /// The spatial weight of the RGBXY hull isn't a positive, finite number.
+    #[error("The spatial weight must be positive and finite, but it is {0}.")]
+    InvalidSpatialWeight(f64),
+
+    /// The tiles of a `TiledDecomposition` are empty, or overlap by more than half of their size.
+    #[error("Tiles of size {tile_size} can't overlap by {overlap} pixels.")]
+    InvalidTileSize { tile_size: u32, overlap: u32 },
+
+    /// A tile handed back to `TiledDecomposition::reconstruct_rows` doesn't cover the part of the
+    /// image its position does.
+    #[error("The decomposition of tile ({tile_x}, {tile_y}) doesn't match the size of that tile.")]
+    TileMismatch { tile_x: u32, tile_y: u32 },
+
+    /// Reading or writing the tiles of a tiled decomposition, or writing out a reconstruction,
+    /// failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// qhull reported an error while building a convex hull or triangulation.
+    #[error("qhull failed: {0}")]
+    Qhull(#[from] qhull_rs::QhullError),
+}
@@ -0,0 +1,423 @@
+use image::{GenericImageView, Rgb};
+use nalgebra::{Const, Vector3};
+use qhull_rs::ConvexHull;
+
+use crate::{ColorSpace, PaletteColor, PaletteOptions, RecolorError, SourcePixel};
+use crate::palette::color_counts;
+
+/// A method of choosing a palette for an image.
+///
+/// Different images suit different methods, so this lets the method be picked per image.
+/// `HullSimplification` is the method behind `compute_palette`, and its palettes can be used to
+/// decompose the image as is. Other methods, like `KMeans` and `MedianCut`, pick colors from the
+/// middle of the image's colors, so their palettes should be wrapped in `ExpandToHull` before they
+/// are passed to `DecomposedImage::new`.
+///
+/// The trait is generic over the pixel type rather than its method, so a method picked at runtime
+/// can be kept in a `Box<dyn PaletteExtractor<P>>`.
+pub trait PaletteExtractor<P: SourcePixel> {
+    /// Choose a palette for `img`. Fully transparent pixels are ignored.
+    fn extract(&self, img: &dyn GenericImageView<Pixel = P>) -> Result<Vec<Rgb<u8>>, RecolorError>;
+}
+
+/// A sized wrapper around a `dyn GenericImageView`, since `GenericImageView::pixels` is only
+/// available for sized images.
+struct DynImage<'a, P>(&'a dyn GenericImageView<Pixel = P>);
+
+impl<P: SourcePixel> GenericImageView for DynImage<'_, P> {
+    type Pixel = P;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    // Deprecated, but image 0.24 still requires it.
+    #[allow(deprecated)]
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        self.0.bounds()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.0.get_pixel(x, y)
+    }
+}
+
+/// Simplify the convex hull of the image's colors, the same as `compute_palette_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct HullSimplification(pub PaletteOptions);
+
+impl<P: SourcePixel> PaletteExtractor<P> for HullSimplification {
+    fn extract(&self, img: &dyn GenericImageView<Pixel = P>) -> Result<Vec<Rgb<u8>>, RecolorError> {
+        crate::compute_palette_with_options(&DynImage(img), &self.0).map(|report| report.palette)
+    }
+}
+
+/// Cluster the image's colors with k-means, using the mean color of each cluster.
+///
+/// The clusters are seeded with the most common color followed by the colors farthest from the
+/// seeds so far, so the result is deterministic and tends to include the image's extreme colors.
+#[derive(Clone, Debug)]
+pub struct KMeans {
+    /// The number of clusters. The palette has fewer colors if the image does, and a size of 0 is
+    /// treated as 1.
+    pub palette_size: usize,
+    /// The maximum number of iterations to run if the clusters don't settle sooner.
+    pub max_iterations: usize,
+}
+
+impl KMeans {
+    /// Cluster the colors into `palette_size` clusters, running at most 50 iterations.
+    ///
+    /// If the image has fewer than `palette_size` distinct visible colors, every color gets its own
+    /// cluster and the palette is just those colors. A `palette_size` of 0 gives a single cluster,
+    /// the same as 1.
+    pub fn new(palette_size: usize) -> Self {
+        KMeans {
+            palette_size,
+            max_iterations: 50,
+        }
+    }
+}
+
+impl<P: SourcePixel> PaletteExtractor<P> for KMeans {
+    fn extract(&self, img: &dyn GenericImageView<Pixel = P>) -> Result<Vec<Rgb<u8>>, RecolorError> {
+        let colors = sorted_color_counts(&DynImage(img))?;
+        let k = self.palette_size.clamp(1, colors.len());
+        let nearest = |centers: &[Vector3<f64>], color: &Vector3<f64>| centers.iter()
+            .map(|center| (center - color).norm_squared())
+            .enumerate()
+            .min_by(|(_, l), (_, r)| l.total_cmp(r))
+            .unwrap();
+
+        let mut centers = vec![colors[0].0];
+        while centers.len() < k {
+            let (farthest, _) = colors.iter()
+                .map(|(color, _)| (color, nearest(&centers, color).1))
+                .max_by(|(_, l), (_, r)| l.total_cmp(r))
+                .unwrap();
+            centers.push(*farthest);
+        }
+
+        let mut assignments = vec![usize::MAX; colors.len()];
+        for _ in 0..self.max_iterations {
+            let mut changed = false;
+            for (assignment, (color, _)) in assignments.iter_mut().zip(&colors) {
+                let (index, _) = nearest(&centers, color);
+                if *assignment != index {
+                    *assignment = index;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break
+            }
+            let mut sums = vec![(Vector3::zeros(), 0.0); k];
+            for (&assignment, (color, count)) in assignments.iter().zip(&colors) {
+                sums[assignment].0 += color * *count;
+                sums[assignment].1 += *count;
+            }
+            for (center, (sum, weight)) in centers.iter_mut().zip(sums) {
+                // A cluster can't end up empty, since its center is always nearest to the color
+                // it was seeded with, but guard against it anyway.
+                if weight > 0.0 {
+                    *center = sum / weight;
+                }
+            }
+        }
+        Ok(to_palette(&centers))
+    }
+}
+
+/// Repeatedly split the box of colors with the longest side at its median, using the mean color of
+/// each box.
+#[derive(Clone, Debug)]
+pub struct MedianCut {
+    /// The number of boxes. The palette has fewer colors if the image does, and a size of 0 is
+    /// treated as 1.
+    pub palette_size: usize,
+}
+
+impl MedianCut {
+    /// Split the colors into `palette_size` boxes.
+    ///
+    /// If the image has fewer than `palette_size` distinct visible colors, the splitting stops
+    /// once every box holds a single color, so the palette is just those colors. A `palette_size`
+    /// of 0 leaves all of the colors in one box, the same as 1.
+    pub fn new(palette_size: usize) -> Self {
+        MedianCut { palette_size }
+    }
+}
+
+impl<P: SourcePixel> PaletteExtractor<P> for MedianCut {
+    fn extract(&self, img: &dyn GenericImageView<Pixel = P>) -> Result<Vec<Rgb<u8>>, RecolorError> {
+        let mut boxes = vec![sorted_color_counts(&DynImage(img))?];
+        while boxes.len() < self.palette_size {
+            // The colors are unique, so any box with more than one color can be split.
+            let longest = boxes.iter()
+                .enumerate()
+                .filter(|(_, colors)| colors.len() > 1)
+                .map(|(index, colors)| {
+                    let (axis, length) = (0..3)
+                        .map(|axis| {
+                            let (min, max) = colors.iter().fold(
+                                (f64::INFINITY, f64::NEG_INFINITY),
+                                |(min, max), (color, _)| {
+                                    (min.min(color[axis]), max.max(color[axis]))
+                                },
+                            );
+                            (axis, max - min)
+                        })
+                        .max_by(|(_, l), (_, r)| l.total_cmp(r))
+                        .unwrap();
+                    (index, axis, length)
+                })
+                .max_by(|(_, _, l), (_, _, r)| l.total_cmp(r));
+            let Some((index, axis, _)) = longest else {
+                break
+            };
+
+            let mut lower = boxes.swap_remove(index);
+            lower.sort_by(|(l, _), (r, _)| l[axis].total_cmp(&r[axis]));
+            let half: f64 = lower.iter().map(|(_, count)| *count).sum::<f64>() / 2.0;
+            let mut seen = 0.0;
+            let median = lower.iter()
+                .position(|(_, count)| {
+                    seen += *count;
+                    seen >= half
+                })
+                .unwrap_or(0);
+            // Both halves need at least one color.
+            let upper = lower.split_off((median + 1).min(lower.len() - 1));
+            boxes.push(lower);
+            boxes.push(upper);
+        }
+
+        let centers: Vec<Vector3<f64>> = boxes.iter()
+            .map(|colors| {
+                let (sum, weight) = colors.iter().fold(
+                    (Vector3::zeros(), 0.0),
+                    |(sum, weight), (color, count)| (sum + color * *count, weight + *count),
+                );
+                sum / weight
+            })
+            .collect();
+        Ok(to_palette(&centers))
+    }
+}
+
+/// Run another extractor and then pass its palette through `expand_to_hull`.
+#[derive(Clone, Debug, Default)]
+pub struct ExpandToHull<E> {
+    /// The extractor whose palette is expanded.
+    pub extractor: E,
+    /// The color space to expand the palette's hull in. This should be the color space the image
+    /// will be decomposed in, `ImageWeightsOptions::color_space`.
+    pub color_space: ColorSpace,
+}
+
+impl<E> ExpandToHull<E> {
+    /// Expand the palettes of `extractor` in the default color space.
+    pub fn new(extractor: E) -> Self {
+        ExpandToHull {
+            extractor,
+            color_space: ColorSpace::default(),
+        }
+    }
+}
+
+impl<P: SourcePixel, E: PaletteExtractor<P>> PaletteExtractor<P> for ExpandToHull<E> {
+    fn extract(&self, img: &dyn GenericImageView<Pixel = P>) -> Result<Vec<Rgb<u8>>, RecolorError> {
+        let palette = self.extractor.extract(img)?;
+        expand_to_hull(&palette, &DynImage(img), self.color_space)
+    }
+}
+
+/// Turn any palette into a valid decomposition palette for `img`.
+///
+/// The palette's convex hull is scaled up about its center until it contains every visible pixel
+/// of `img`, so that every pixel can be reconstructed. The scaled colors are clamped to the RGB
+/// cube, which can leave some pixels outside of the hull again. Finally, any colors that aren't
+/// vertices of the hull are dropped, so the result never has redundant colors and can be passed to
+/// `DecomposedImage::new`. The order of the remaining colors is kept.
+///
+/// The hull is built in `color_space`, which should be the color space the image will be
+/// decomposed in, since a palette's hull has a different shape in each of them.
+///
+/// Returns an error if the palette's colors don't span all 3 dimensions.
+pub fn expand_to_hull<P>(
+    palette: &[Rgb<u8>],
+    img: &impl GenericImageView<Pixel = P>,
+    color_space: ColorSpace,
+) -> Result<Vec<Rgb<u8>>, RecolorError>
+    where P: SourcePixel,
+{
+    let to_point = |c: &Rgb<u8>| Vector3::from(color_space.from_srgb(c.to_rgb()));
+    let points: Vec<Vector3<f64>> = palette.iter().map(to_point).collect();
+    if !crate::is_full_dimensional(&points) {
+        return Err(RecolorError::DegenerateHull("palette colors"))
+    }
+    let ch = ConvexHull::<Const<3>>::try_from_vectors(&points)?;
+    let center = ch.vertices()
+        .map(|v| v.point())
+        .sum::<Vector3<f64>>() / ch.vertices().len() as f64;
+
+    // A point `p` is inside the hull scaled by `scale` exactly when, for every facet,
+    // `normal.dot(p - center) <= scale * distance`, where `distance` is the distance from the
+    // center to the facet.
+    let colors: Vec<Vector3<f64>> = color_counts(img).into_iter()
+        .map(|(color, _)| Vector3::from(color_space.from_srgb(color.into())))
+        .collect();
+    let mut scale = 1.0f64;
+    for facet in ch.facets() {
+        let distance = -(facet.normal().dot(&center) + facet.offset());
+        for color in &colors {
+            scale = scale.max(facet.normal().dot(&(color - center)) / distance);
+        }
+    }
+    let expanded: Vec<Vector3<f64>> = points.iter()
+        .map(|p| {
+            let p = center + (p - center) * scale;
+            Vector3::from(color_space.to_srgb(p.into())).map(|c| c.clamp(0.0, 1.0))
+        })
+        .collect();
+    let expanded = to_palette(&expanded);
+
+    // Clamping and rounding can flatten the hull or sink colors inside of it.
+    let points: Vec<Vector3<f64>> = expanded.iter().map(to_point).collect();
+    if !crate::is_full_dimensional(&points) {
+        return Err(RecolorError::DegenerateHull("palette colors"))
+    }
+    let ch = ConvexHull::<Const<3>>::try_from_vectors(&points)?;
+    Ok(expanded.into_iter()
+        .zip(&points)
+        .filter(|(_, point)| ch.vertices().any(|v| v.point() == *point))
+        .map(|(color, _)| color)
+        .collect())
+}
+
+/// The unique visible colors of `img` and their counts, sorted from the most to the least common
+/// so the extractors don't depend on the order of a `HashMap`.
+fn sorted_color_counts<P>(img: &impl GenericImageView<Pixel = P>)
+    -> Result<Vec<(Vector3<f64>, f64)>, RecolorError>
+    where P: SourcePixel,
+{
+    let mut colors = color_counts(img);
+    if colors.is_empty() {
+        return Err(RecolorError::DegenerateHull("image colors"))
+    }
+    colors.sort_by(|(lc, ln), (rc, rn)| {
+        rn.total_cmp(ln)
+            .then_with(|| lc.iter().zip(rc.iter()).fold(
+                std::cmp::Ordering::Equal,
+                |ord, (l, r)| ord.then_with(|| l.total_cmp(r)),
+            ))
+    });
+    Ok(colors)
+}
+
+/// Round `colors` to 8 bits, dropping the colors that round to the same value.
+fn to_palette(colors: &[Vector3<f64>]) -> Vec<Rgb<u8>> {
+    let mut palette: Vec<Rgb<u8>> = vec![];
+    for color in colors {
+        let color = Rgb::<u8>::from_rgb([color.x, color.y, color.z]);
+        if !palette.contains(&color) {
+            palette.push(color);
+        }
+    }
+    palette
+}
+
+
+#[test]
+fn test_extractors_give_decomposition_palettes() {
+    let img = crate::test_util::test_image();
+    let weights = crate::ImageWeights::new(&img).unwrap();
+
+    let kmeans = KMeans::new(6).extract(&img).unwrap();
+    assert_eq!(kmeans.len(), 6);
+    let median_cut = MedianCut::new(6).extract(&img).unwrap();
+    assert_eq!(median_cut.len(), 6);
+
+    for palette in [
+        ExpandToHull::new(KMeans::new(6)).extract(&img).unwrap(),
+        ExpandToHull::new(MedianCut::new(6)).extract(&img).unwrap(),
+        ExpandToHull::new(HullSimplification::default()).extract(&img).unwrap(),
+    ] {
+        assert!(palette.len() >= 4);
+        assert!(crate::DecomposedImage::new(&weights, &palette).is_ok());
+    }
+}
+
+#[test]
+fn test_expand_to_hull() {
+    use image::ImageBuffer;
+
+    // A small tetrahedron in the middle of the image's colors, plus a color inside of it.
+    let palette = [
+        Rgb([100, 100, 100]), Rgb([140, 100, 100]), Rgb([100, 140, 100]), Rgb([100, 100, 140]),
+        Rgb([105, 105, 105]),
+    ];
+    // Close enough that the expanded palette doesn't need to be clamped to the RGB cube.
+    let img = ImageBuffer::from_fn(4, 4, |x, y| {
+        Rgb([(x * 10 + 95) as u8, (y * 10 + 95) as u8, ((x + y) * 5 + 95) as u8])
+    });
+    let expanded = expand_to_hull(&palette, &img, ColorSpace::Srgb).unwrap();
+    assert_eq!(expanded.len(), 4);
+
+    let points: Vec<Vector3<f64>> = expanded.iter().map(|c| Vector3::from(c.to_rgb())).collect();
+    let ch = ConvexHull::<Const<3>>::try_from_vectors(&points).unwrap();
+    for (_, _, pixel) in img.enumerate_pixels() {
+        let pixel = Vector3::from(pixel.to_rgb());
+        // Rounding the palette to 8 bits can leave a pixel a hair outside.
+        assert!(ch.facets().all(|f| f.normal().dot(&pixel) + f.offset() <= 1.0 / 255.0));
+    }
+}
+
+#[test]
+fn test_extractor_trait_objects() {
+    let img = crate::test_util::test_image();
+    let extractors: Vec<Box<dyn PaletteExtractor<Rgb<u8>>>> = vec![
+        Box::new(KMeans::new(5)),
+        Box::new(MedianCut::new(5)),
+        Box::new(ExpandToHull::new(KMeans::new(5))),
+    ];
+    for extractor in &extractors {
+        assert!(!extractor.extract(&img).unwrap().is_empty());
+    }
+
+    // A size of 0 is treated as 1, and a size larger than the number of colors gives every color.
+    assert_eq!(KMeans::new(0).extract(&img).unwrap().len(), 1);
+    assert_eq!(MedianCut::new(0).extract(&img).unwrap().len(), 1);
+    let two_tone = image::ImageBuffer::from_fn(4, 4, |x, _| {
+        if x < 2 { Rgb([10u8, 20, 30]) } else { Rgb([200, 210, 220]) }
+    });
+    assert_eq!(KMeans::new(5).extract(&two_tone).unwrap().len(), 2);
+    assert_eq!(MedianCut::new(5).extract(&two_tone).unwrap().len(), 2);
+}
+
+#[test]
+fn test_expand_to_hull_color_space() {
+    let palette = [
+        Rgb([100, 100, 100]), Rgb([140, 100, 100]), Rgb([100, 140, 100]), Rgb([100, 100, 140]),
+    ];
+    let img = image::ImageBuffer::from_fn(4, 4, |x, y| {
+        Rgb([(x * 20 + 100) as u8, (y * 20 + 100) as u8, ((x + y) * 10 + 100) as u8])
+    });
+    // How far the farthest pixel is outside of the expanded palette's hull in Oklab.
+    let distance_outside = |color_space: ColorSpace| {
+        let expanded = expand_to_hull(&palette, &img, color_space).unwrap();
+        let to_point = |c: [f64; 3]| Vector3::from(ColorSpace::Oklab.from_srgb(c));
+        let points: Vec<_> = expanded.iter().map(|c| to_point(c.to_rgb())).collect();
+        let ch = ConvexHull::<Const<3>>::try_from_vectors(&points).unwrap();
+        img.pixels()
+            .flat_map(|pixel| {
+                let pixel = to_point(pixel.to_rgb());
+                ch.facets().map(move |f| f.normal().dot(&pixel) + f.offset())
+            })
+            .fold(f64::NEG_INFINITY, f64::max)
+    };
+    // A hull that contains every pixel in sRGB doesn't in Oklab, so the hull has to be expanded
+    // in the color space the image is decomposed in.
+    assert!(distance_outside(ColorSpace::Srgb) > 0.01);
+    assert!(distance_outside(ColorSpace::Oklab) <= 0.0);
+}
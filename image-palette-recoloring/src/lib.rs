@@ -1,41 +1,282 @@
-use image::{GenericImageView, ImageBuffer, Luma, Rgb};
+use std::collections::{HashMap, HashSet};
+
+use image::{GenericImageView, ImageBuffer, Luma, Rgb, Rgba};
 use qhull_rs::{ConvexHull, Delaunay};
-use nalgebra::{Const, DMatrix, Dyn, Matrix, Vector3, Vector5, Vector6};
+use qhull_rs::delaunay::{Simplex, SimplexSearcher};
+use nalgebra::{Const, DMatrix, DVector, Dyn, Matrix, OMatrix, SVector, Vector3, Vector5, Vector6};
 
+mod collapse;
+mod color_space;
+mod error;
+mod extract;
 mod palette;
+mod persist;
+mod pixel;
+mod tiled;
 mod triangle_distance;
+#[cfg(test)]
+mod test_util;
+
+pub use color_space::ColorSpace;
+pub use error::RecolorError;
+pub use extract::{
+    expand_to_hull, ExpandToHull, HullSimplification, KMeans, MedianCut, PaletteExtractor,
+};
+pub use palette::{
+    compute_palette, compute_palette_ladder, compute_palette_with_options, LadderPalette,
+    PaletteErrorMetric, PaletteOptions, PaletteReport, PaletteStep, PaletteStopReason,
+};
+pub use persist::LayerPrecision;
+pub use pixel::{PaletteColor, SourcePixel};
+pub use tiled::{TileOptions, TiledDecomposition};
+
+/// `Sync` if the `rayon` feature is enabled, since the image is then shared between threads while
+/// computing `ImageWeights`. Without the feature, every type implements it.
+#[cfg(feature = "rayon")]
+pub trait MaybeSync: Sync { }
+#[cfg(feature = "rayon")]
+impl<T: Sync + ?Sized> MaybeSync for T { }
+
+/// `Sync` if the `rayon` feature is enabled, since the image is then shared between threads while
+/// computing `ImageWeights`. Without the feature, every type implements it.
+#[cfg(not(feature = "rayon"))]
+pub trait MaybeSync { }
+#[cfg(not(feature = "rayon"))]
+impl<T: ?Sized> MaybeSync for T { }
+
+/// Options that control how `ImageWeights` are computed.
+#[derive(Clone, Debug)]
+pub struct ImageWeightsOptions {
+    /// Leave fully transparent pixels out of the RGBXY convex hull.
+    ///
+    /// The color of a fully transparent pixel is usually meaningless, but it still affects the
+    /// shape of the hull. When this is set, such pixels don't contribute to the hull and are given
+    /// no weights at all, so they reconstruct as transparent black.
+    ///
+    /// This has no effect for images without an alpha channel.
+    pub exclude_transparent: bool,
+    /// The color space to build the RGBXY hull in. Every `DecomposedImage` of these weights is
+    /// computed and reconstructed in the same color space.
+    pub color_space: ColorSpace,
+    /// How much the position of a pixel counts relative to its color in the RGBXY hull.
+    ///
+    /// The x and y coordinates are divided by the longer side of the image, so that they run from
+    /// 0 to 1 along it without distorting the shorter side, and then multiplied by this. Larger
+    /// values give layers that are more spatially coherent, while smaller values give sparser
+    /// layers and a hull with fewer vertices. Must be positive and finite. Defaults to 1.
+    pub spatial_weight: f64,
+    /// Which pixels the RGBXY hull and its triangulation are built from. The weights of every
+    /// pixel are still computed against the triangulation.
+    pub hull_sampling: HullSampling,
+}
+
+/// Which pixels `ImageWeights::with_options` builds the RGBXY hull out of.
+///
+/// Building the 5D hull and its Delaunay triangulation is the most expensive part of computing
+/// the weights of a large image. Building them out of fewer points makes this much cheaper, at the
+/// cost of weights that only approximate the ones of the full hull. Pixels that end up outside of
+/// the smaller hull are projected onto the nearest simplex of its triangulation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HullSampling {
+    /// Use every pixel.
+    #[default]
+    Full,
+    /// Use every pixel whose x and y are both multiples of this factor, along with the last row and
+    /// column of the image. The hull sees only some of the colors of the image, so this is best
+    /// suited to smooth images such as photos. A factor of 0 or 1 uses every pixel.
+    Downsample(u32),
+    /// Use every color of the image, but move each pixel to the nearest point of a grid with cells
+    /// of this many pixels and leave out the points that are then identical. Every color is still
+    /// in the hull, so this works better than `Downsample` for images with few colors. A cell size
+    /// of 0 or 1 uses every pixel.
+    Deduplicate(u32),
+}
+
+impl Default for ImageWeightsOptions {
+    fn default() -> Self {
+        ImageWeightsOptions {
+            exclude_transparent: false,
+            color_space: ColorSpace::default(),
+            spatial_weight: 1.0,
+            hull_sampling: HullSampling::default(),
+        }
+    }
+}
 
-pub use palette::compute_palette;
+/// What `DecomposedImage::with_options` does with palette colors that are inside the convex hull
+/// of the rest of the palette.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedundantColorHandling {
+    /// Return `RecolorError::RedundantColors`. This is what `DecomposedImage::new` does.
+    #[default]
+    Error,
+    /// Leave the redundant colors out of the decomposition. Their channels are always empty, so
+    /// changing them has no effect on the reconstruction. `redundant_colors` reports which colors
+    /// these are.
+    Drop,
+    /// Give the redundant colors channels of their own by decomposing each pixel against a
+    /// Delaunay triangulation of the whole palette, instead of the star triangulation of the
+    /// hull. Pixels close to a redundant color are made up mostly of that color.
+    Triangulate,
+}
+
+/// Which palette color `DecomposedImage::with_options` uses as the star vertex.
+///
+/// The palette is split into tetrahedrons that all share one color, the star. Pixels are mostly
+/// made up of the star color plus the colors of one facet of the palette's hull, so the choice of
+/// star changes which layers each pixel ends up in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StarVertex {
+    /// The color closest to black. This works well for most images.
+    #[default]
+    NearestBlack,
+    /// The color closest to white, which gives more intuitive layers for images dominated by
+    /// light tones.
+    NearestWhite,
+    /// The color at this index of the palette. It must be a vertex of the palette's hull.
+    Index(usize),
+    /// Try every color and keep the one that leaves the fewest nonzero values in the layers. This
+    /// decomposes the palette once per color, so it is slower.
+    MinimizeSparsity,
+}
+
+/// Options that control how a `DecomposedImage` is computed.
+#[derive(Clone, Debug, Default)]
+pub struct DecompositionOptions {
+    /// What to do with colors that aren't vertices of the palette's 3D convex hull.
+    pub redundant_colors: RedundantColorHandling,
+    /// Which color to use as the star vertex. `DecomposedImage::star_vertex` reports the color
+    /// that was used.
+    pub star_vertex: StarVertex,
+}
 
 /// An image represented in terms of the vertices of a 5D RGBXY convex hull.
 ///
 /// This is the first step recoloring an image. Since the process of calculating the per-vertex
 /// weights is costly, it is recommended that you keep this data structure around if you plan on
-/// creating multiple decompositions of it.
+/// creating multiple decompositions of it. `ImageWeights::save` and `ImageWeights::load` can be
+/// used to keep it around between runs of your program.
 pub struct ImageWeights {
     weights: nalgebra_sparse::CsrMatrix<f64>,
     ch_rgb_vertices: Vec<Vector3<f64>>,
+    color_space: ColorSpace,
+    spatial_weight: f64,
+    alpha: Option<Vec<f32>>,
     width: u32,
     height: u32,
+    // The hash recorded in the file these weights were loaded from. Hashing reads every pixel, so
+    // it is left out of weights computed in this process and only done when saving.
+    source_hash: Option<u64>,
 }
 
 impl ImageWeights {
     /// Compute the per-vertex weights of the `img`.
-    pub fn new(img: &impl GenericImageView<Pixel = Rgb<u8>>) -> Self {
+    ///
+    /// If the `rayon` feature is enabled, the per-pixel part of this computation is spread across
+    /// multiple threads. The result is the same regardless of whether the feature is enabled.
+    ///
+    /// If the pixels don't span all 5 RGBXY dimensions, as is the case for a two-tone image, every
+    /// unique color is used as a vertex instead of the vertices of the RGBXY hull. Returns an error
+    /// for a solid-color image, since there is nothing to decompose.
+    pub fn new<P>(img: &(impl GenericImageView<Pixel = P> + MaybeSync))
+        -> Result<Self, RecolorError>
+        where P: SourcePixel,
+    {
+        Self::with_options(img, &ImageWeightsOptions::default())
+    }
+
+    /// Compute the per-vertex weights of the `img` using the provided options.
+    pub fn with_options<P>(
+        img: &(impl GenericImageView<Pixel = P> + MaybeSync),
+        options: &ImageWeightsOptions,
+    ) -> Result<Self, RecolorError>
+        where P: SourcePixel,
+    {
+        // Fully transparent pixels may be left out of the hull entirely. These pixels are skipped
+        // for both the hull and the weights.
+        let is_excluded = |pix: &P| options.exclude_transparent && pix.alpha() == Some(0.0);
+
+        let spatial_weight = options.spatial_weight;
+        if !(spatial_weight > 0.0 && spatial_weight.is_finite()) {
+            return Err(RecolorError::InvalidSpatialWeight(spatial_weight))
+        }
+        let spatial_scale = spatial_weight / std::cmp::max(img.width(), img.height()) as f64;
+        let to_rgbxy = |x: u32, y: u32, pix: &P| {
+            let [r, g, b] = options.color_space.from_srgb(pix.rgb());
+            Vector5::new(r, g, b, x as f64 * spatial_scale, y as f64 * spatial_scale)
+        };
+
         // We want to represent each 5d-pixel in the image in terms of vertices of the 5d convex
         // hull of all the pixels. To accomplish this, we compute the delaunay triangulation of
         // that convex hull and then Using the triangulation, we find a simplex that contains the
         // pixel and compute the barycentric coordinates.
 
-        let ch: ConvexHull<Const<5>> = img.pixels()
-            .map(|(x, y, pix)| [
-                    pix[0] as f64 / 255.0,
-                    pix[1] as f64 / 255.0,
-                    pix[2] as f64 / 255.0,
-                    x as f64 / img.width() as f64,
-                    y as f64 / img.height() as f64
-                ].into())
-            .collect();
+        let points: Vec<Vector5<f64>> = match options.hull_sampling {
+            HullSampling::Full => {
+                // A pixel between two pixels of its own color, horizontally or vertically, is the
+                // midpoint of them in RGBXY, so it can't be a vertex of the hull. Leaving these out
+                // doesn't change the hull, and flat images are mostly made up of them.
+                let same_color = |pix: &P, x: u32, y: u32| {
+                    let other = img.get_pixel(x, y);
+                    !is_excluded(&other) && other.rgb() == pix.rgb()
+                };
+                let is_interior = |x: u32, y: u32, pix: &P| {
+                    (x > 0 && x + 1 < img.width()
+                        && same_color(pix, x - 1, y) && same_color(pix, x + 1, y))
+                    || (y > 0 && y + 1 < img.height()
+                        && same_color(pix, x, y - 1) && same_color(pix, x, y + 1))
+                };
+                img.pixels()
+                    .filter(|(_, _, pix)| !is_excluded(pix))
+                    .filter(|(x, y, pix)| !is_interior(*x, *y, pix))
+                    .map(|(x, y, pix)| to_rgbxy(x, y, &pix))
+                    .collect()
+            },
+            HullSampling::Downsample(factor) => {
+                let factor = std::cmp::max(factor, 1);
+                // Keep the last row and column so the hull spans the whole image.
+                let on_grid = |i: u32, len: u32| i.is_multiple_of(factor) || i == len - 1;
+                img.pixels()
+                    .filter(|(x, y, _)| on_grid(*x, img.width()) && on_grid(*y, img.height()))
+                    .filter(|(_, _, pix)| !is_excluded(pix))
+                    .map(|(x, y, pix)| to_rgbxy(x, y, &pix))
+                    .collect()
+            },
+            HullSampling::Deduplicate(cell_size) => {
+                let cell_size = std::cmp::max(cell_size, 1);
+                let snap = |i: u32, len: u32| {
+                    std::cmp::min((i + cell_size / 2) / cell_size * cell_size, len - 1)
+                };
+                let mut seen = HashSet::new();
+                img.pixels()
+                    .filter(|(_, _, pix)| !is_excluded(pix))
+                    .map(|(x, y, pix)| to_rgbxy(snap(x, img.width()), snap(y, img.height()), &pix))
+                    .filter(|point| seen.insert(point.map(f64::to_bits)))
+                    .collect()
+            },
+        };
+        // qhull can't build a hull out of points that all lie in a lower dimensional subspace, so
+        // catch that case up front. Those images usually have very few colors, so each pixel is
+        // simply represented by its own color.
+        if !is_full_dimensional(&points) {
+            let (weights, ch_rgb_vertices) =
+                unique_color_weights(img, options.color_space, is_excluded);
+            if ch_rgb_vertices.len() < 2 {
+                return Err(RecolorError::DegenerateHull("image pixels"))
+            }
+            return Ok(ImageWeights {
+                weights,
+                ch_rgb_vertices,
+                color_space: options.color_space,
+                spatial_weight,
+                alpha: image_alpha(img),
+                width: img.width(),
+                height: img.height(),
+                source_hash: None,
+            })
+        }
+        let ch = ConvexHull::<Const<5>>::try_from_vectors(&points)?;
+        drop(points);
         let ch_vertices: Vec<_> = ch.vertices()
             .map(|v| v.point())
             .map(|p| [p[0], p[1], p[2], p[3], p[4]])
@@ -45,50 +286,108 @@ impl ImageWeights {
         let _ = ch;
 
         // Build a triangulation of the convex hull's vertices
-        let tri = Delaunay::<Const<5>>::from_arrays(&ch_vertices[..]);
+        let tri = Delaunay::<Const<5>>::try_from_arrays(&ch_vertices[..])?;
 
         let vertex_count = ch_vertices.len();
+
+        // When the hull is built from a subset of the pixels, the remaining pixels can be outside
+        // of it. Those are projected onto the simplices on the boundary of the triangulation.
+        let (boundary, max_tolerance) = match options.hull_sampling {
+            HullSampling::Full => (vec![], f64::INFINITY),
+            _ => {
+                let boundary: Vec<_> = tri.simplices()
+                    .filter(|simplex| simplex.neighbors().any(|neighbor| neighbor.is_none()))
+                    .collect();
+                (boundary, 1e-6)
+            },
+        };
         let row_count = (img.height() * img.width()) as usize;
 
+        // For each pixel, find the simplex that contains the pixel.
+        //
+        // The pixels are processed in bands of rows, each with its own `SimplexSearcher`. This
+        // lets the bands be computed in parallel when the `rayon` feature is enabled. The serial
+        // path walks the exact same bands so the output is identical either way.
+        const ROWS_PER_BAND: u32 = 32;
+        let band_count = img.height().div_ceil(ROWS_PER_BAND);
+        let compute_band = |band: u32| {
+            let y_start = band * ROWS_PER_BAND;
+            let y_end = std::cmp::min(y_start + ROWS_PER_BAND, img.height());
+            let pixel_count = ((y_end - y_start) * img.width()) as usize;
+            let mut row_indices = Vec::with_capacity(pixel_count);
+            let mut col_indices = Vec::with_capacity(pixel_count);
+            let mut values = Vec::with_capacity(pixel_count);
+
+            let mut simplex_searcher = tri.simplex_searcher();
+            let mut bcoords = Vector6::from_element(0.0);
+            // The simplex of the last pixel of each color. Pixels of the same color are usually
+            // close to each other, and in flat images most of them share a simplex, so trying it
+            // first skips most of the searches.
+            let mut color_simplices = HashMap::new();
+            for y in y_start..y_end {
+                for x in 0..img.width() {
+                    let pix = img.get_pixel(x, y);
+                    if is_excluded(&pix) {
+                        continue
+                    }
+                    let point = to_rgbxy(x, y, &pix);
+                    const INITAL_TOLERANCE: f64 = 1e-10;
+                    let color = pix.rgb().map(f64::to_bits);
+                    let cached = color_simplices.get(&color).copied().filter(|simplex| {
+                        simplex_searcher.barycentric_coords_mut(*simplex, point, &mut bcoords);
+                        bcoords.iter()
+                            .all(|c| *c >= -INITAL_TOLERANCE && *c <= 1.0 + INITAL_TOLERANCE)
+                    });
+                    // Here we _must_ find a containing simplex for every pixel. To that end, we
+                    // start with a relatively tight tolerance which should work for the majority
+                    // of pixels and then for the pixels that fail, we iteratively loosen the
+                    // tolerance until we get a match. Pixels that are still outside of a sampled
+                    // hull at the maximum tolerance are projected onto it instead.
+                    simplex_searcher.set_eps(INITAL_TOLERANCE);
+                    let simplex = cached.unwrap_or_else(|| loop {
+                        let simplex = simplex_searcher.find_simplex_mut(&point, &mut bcoords);
+                        if let Some(simplex) = simplex {
+                            break simplex
+                        }
+                        let current_tolerance = simplex_searcher.eps();
+                        if current_tolerance * 2.0 > max_tolerance {
+                            break nearest_boundary_simplex(
+                                &simplex_searcher,
+                                &boundary,
+                                &point,
+                                &mut bcoords,
+                            )
+                        }
+                        simplex_searcher.set_eps(current_tolerance * 2.0);
+                    });
+                    color_simplices.insert(color, simplex);
+                    let i = (y * img.width() + x) as usize;
+                    for (vert, value) in simplex.vertices().zip(bcoords.as_slice().iter()) {
+                        row_indices.push(i);
+                        col_indices.push(vert.index());
+                        values.push(*value);
+                    }
+                }
+            }
+            (row_indices, col_indices, values)
+        };
+
+        #[cfg(feature = "rayon")]
+        let bands: Vec<_> = {
+            use rayon::prelude::*;
+            (0..band_count).into_par_iter().map(compute_band).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let bands: Vec<_> = (0..band_count).map(compute_band).collect();
+
         // We'll be building a COO matrix, so we need to collect the following vecs
         let mut row_indices = Vec::with_capacity(row_count);
         let mut col_indices = Vec::with_capacity(row_count);
         let mut values = Vec::with_capacity(row_count);
-
-        // For each pixel, find the convex hull that contains the pixel.
-        let mut simplex_searcher = tri.simplex_searcher();
-        let mut bcoords = Vector6::from_element(0.0);
-        // TODO: Look into using rayon to parallelize this computation. Each pixel can be computed
-        //       independently with the only minor performance downside.
-        for (i, (x, y, pix)) in img.pixels().enumerate() {
-            // Here we _must_ find a containing simplex for every pixel. To that end, we start with
-            // a relatively tight tolerance which should work for the majority of pixels and then
-            // for the pixels that fail, we iteratively loosen the tolerance until we get a match.
-            const INITAL_TOLERANCE: f64 = 1e-10;
-            simplex_searcher.set_eps(INITAL_TOLERANCE);
-            let simplex = loop {
-                let simplex = simplex_searcher.find_simplex_mut(
-                    &Vector5::new(
-                        pix[0] as f64 / 255.0,
-                        pix[1] as f64 / 255.0,
-                        pix[2] as f64 / 255.0,
-                        x as f64 / img.width() as f64,
-                        y as f64 / img.height() as f64,
-                    ),
-                    &mut bcoords,
-                );
-                if let Some(simplex) = simplex {
-                    break simplex
-                } else {
-                    let current_tolerance = simplex_searcher.eps();
-                    simplex_searcher.set_eps(current_tolerance * 2.0);
-                }
-            };
-            for (vert, value) in simplex.vertices().zip(bcoords.as_slice().iter()) {
-                row_indices.push(i);
-                col_indices.push(vert.index());
-                values.push(*value);
-            }
+        for (band_rows, band_cols, band_values) in bands {
+            row_indices.extend(band_rows);
+            col_indices.extend(band_cols);
+            values.extend(band_values);
         }
 
         let coo = nalgebra_sparse::CooMatrix::try_from_triplets(
@@ -97,7 +396,7 @@ impl ImageWeights {
             row_indices,
             col_indices,
             values,
-        ).unwrap();
+        ).expect("every simplex vertex is a vertex of the triangulation");
         let weights = nalgebra_sparse::CsrMatrix::from(&coo);
         let _ = coo;
 
@@ -110,12 +409,16 @@ impl ImageWeights {
             .map(|rgbxy| [rgbxy[0] * 255.0, rgbxy[1] * 255.0, rgbxy[2] * 255.0].into())
             .collect::<Vec<_>>();
 
-        ImageWeights {
+        Ok(ImageWeights {
             weights,
             ch_rgb_vertices,
+            color_space: options.color_space,
+            spatial_weight,
+            alpha: image_alpha(img),
             width: img.width(),
             height: img.height(),
-        }
+            source_hash: None,
+        })
     }
 
     /// The height of the original image
@@ -127,6 +430,110 @@ impl ImageWeights {
     pub fn width(&self) -> u32 {
         self.width
     }
+
+    /// Returns `true` if the original image had an alpha channel.
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
+    /// The color space the weights were computed in.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// The spatial weight the weights were computed with. See
+    /// `ImageWeightsOptions::spatial_weight`.
+    pub fn spatial_weight(&self) -> f64 {
+        self.spatial_weight
+    }
+}
+
+/// The alpha channel of `img`, if it has one.
+///
+/// The alpha channel isn't part of the decomposition, it is simply carried along so it can be
+/// reattached to the reconstructed image.
+fn image_alpha<P: SourcePixel>(img: &impl GenericImageView<Pixel = P>) -> Option<Vec<f32>> {
+    img.pixels()
+        .map(|(_, _, pix)| pix.alpha().map(|a| a as f32))
+        .collect()
+}
+
+/// Find the simplex of `boundary` that `point` is closest to, for a point outside of the
+/// triangulation, and store the barycentric coordinates of the point projected onto it in
+/// `bcoords`.
+///
+/// The projection clamps the negative coordinates to zero and rescales the rest to sum to one. This
+/// isn't exactly the closest point of the simplex, but it is close for points just outside of it.
+fn nearest_boundary_simplex<'a>(
+    searcher: &SimplexSearcher<'a, Const<5>>,
+    boundary: &[Simplex<'a, Const<5>>],
+    point: &Vector5<f64>,
+    bcoords: &mut Vector6<f64>,
+) -> Simplex<'a, Const<5>> {
+    let mut nearest = None;
+    let mut nearest_distance = f64::INFINITY;
+    let mut coords = Vector6::from_element(0.0);
+    for simplex in boundary {
+        searcher.barycentric_coords_mut(*simplex, *point, &mut coords);
+        // Degenerate simplices have no barycentric coordinates.
+        if coords.iter().any(|c| !c.is_finite()) {
+            continue
+        }
+        coords.apply(|c| *c = c.max(0.0));
+        let sum = coords.sum();
+        if sum <= 0.0 {
+            continue
+        }
+        coords /= sum;
+        let projected: Vector5<f64> = simplex.vertices()
+            .zip(coords.iter())
+            .map(|(v, c)| v.point() * *c)
+            .sum();
+        let distance = (projected - point).norm_squared();
+        if distance < nearest_distance {
+            nearest_distance = distance;
+            nearest = Some(*simplex);
+            bcoords.copy_from(&coords);
+        }
+    }
+    nearest.expect("a full dimensional triangulation has non-degenerate boundary simplices")
+}
+
+/// Weights that represent each pixel by its own color, for images whose RGBXY points don't span
+/// enough dimensions to build a hull. The vertices are the unique colors of the image.
+pub(crate) fn unique_color_weights<P: SourcePixel>(
+    img: &impl GenericImageView<Pixel = P>,
+    color_space: ColorSpace,
+    is_excluded: impl Fn(&P) -> bool,
+) -> (nalgebra_sparse::CsrMatrix<f64>, Vec<Vector3<f64>>) {
+    let row_count = (img.height() * img.width()) as usize;
+    let mut row_indices = Vec::with_capacity(row_count);
+    let mut col_indices = Vec::with_capacity(row_count);
+    let mut ch_rgb_vertices = vec![];
+    // f64 isn't hashable, so we key the map on the bit patterns of the components instead.
+    let mut vertex_map = HashMap::new();
+    for (x, y, pix) in img.pixels() {
+        if is_excluded(&pix) {
+            continue
+        }
+        let rgb = pix.rgb();
+        let vertex = *vertex_map.entry(rgb.map(f64::to_bits)).or_insert_with(|| {
+            ch_rgb_vertices.push(Vector3::from(color_space.from_srgb(rgb)) * 255.0);
+            ch_rgb_vertices.len() - 1
+        });
+        row_indices.push((y * img.width() + x) as usize);
+        col_indices.push(vertex);
+    }
+
+    let values = vec![1.0; row_indices.len()];
+    let coo = nalgebra_sparse::CooMatrix::try_from_triplets(
+        row_count,
+        ch_rgb_vertices.len(),
+        row_indices,
+        col_indices,
+        values,
+    ).expect("every pixel's color is a vertex");
+    (nalgebra_sparse::CsrMatrix::from(&coo), ch_rgb_vertices)
 }
 
 /// An image decomposed to a given palette of colors.
@@ -140,6 +547,11 @@ impl ImageWeights {
 /// original image.
 pub struct DecomposedImage {
     matrix: DMatrix<f64>,
+    // The decomposition palette, scaled to the range 0-1.
+    palette: Vec<[f64; 3]>,
+    star: Option<usize>,
+    color_space: ColorSpace,
+    alpha: Option<Vec<f32>>,
     width: u32,
     height: u32,
 }
@@ -147,51 +559,167 @@ pub struct DecomposedImage {
 impl DecomposedImage {
     /// Decompose an image into channels based on a palette colors.
     ///
-    /// The minimum palette size is 4 colors.
+    /// The minimum palette size is 2 colors. Palettes of 4 or more colors are decomposed using the
+    /// 3D convex hull of the palette. A palette of 2 colors is treated as the line segment between
+    /// them, and a palette of 3 colors as the triangle between them, which suits two- and
+    /// three-tone images. Pixels off the segment or triangle are represented by the closest point
+    /// on it.
     ///
     /// If your palette has redundant colors, this method will return an error. Whether a color is
     /// redundant is based on the 3D convex hull of the colors in the palette. This can make it
     /// hard to predict which colors will be redundant, however the output of `compute_palette`
-    /// should never contain redundant colors.
-    pub fn new(img: &ImageWeights, palette: &[Rgb<u8>]) -> Result<Self, String> {
-        if palette.len() < 4 {
-            return Err(format!(
-                "The minimum palette size is 4. Only {} colors were provided.",
-                palette.len()
-            ))
-        }
-        let palette_ch: ConvexHull<Const<3>> = palette.iter()
-            .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64].into())
-            .collect();
+    /// should never contain redundant colors. Use `redundant_colors` to check a palette ahead of
+    /// time, or `with_options` to decompose it anyway.
+    pub fn new<C: PaletteColor>(img: &ImageWeights, palette: &[C])
+        -> Result<Self, RecolorError>
+    {
+        Self::with_options(img, palette, &DecompositionOptions::default())
+    }
 
-        for color in palette {
-            let color = [color[0] as f64, color[1] as f64, color[2] as f64];
-            if palette_ch.vertices().all(|v| v.point().as_slice() != &color[..]) {
-                return Err(
-                    "The palette contains redundant colors (not all colors are present in the 3d\
-                    convex hull of the palette".into()
-                );
-            }
+    /// Decompose an image into channels based on a palette colors using the provided options.
+    ///
+    /// Every color of the palette gets a channel, even if the palette has redundant colors, so the
+    /// reconstruction palettes are always the same size as `palette`.
+    pub fn with_options<C: PaletteColor>(
+        img: &ImageWeights,
+        palette: &[C],
+        options: &DecompositionOptions,
+    ) -> Result<Self, RecolorError> {
+        if palette.len() < 2 {
+            return Err(RecolorError::PaletteTooSmall { min: 2, actual: palette.len() })
         }
-
-        let palette_matrix = crate::palette::compute_star_triangulation_coordinates(
-            &palette,
-            &palette_ch,
-            &img.ch_rgb_vertices[..],
-        );
+        // The RGB vertices of the image weights are in the weights' color space, scaled to the
+        // range 0-255, so the palette needs to be as well.
+        let (palette_matrix, star) = if palette.len() < 4 {
+            let palette_points = palette_in_color_space(palette, img.color_space);
+            let matrix = crate::palette::compute_degenerate_coordinates(
+                &palette_points,
+                &img.ch_rgb_vertices[..],
+            )?;
+            (matrix, None)
+        } else {
+            Self::hull_palette_matrix(img, palette, options)?
+        };
 
         Ok(DecomposedImage {
             matrix: &img.weights * palette_matrix,
+            palette: palette.iter().map(|c| c.to_rgb()).collect(),
+            star,
+            color_space: img.color_space,
+            alpha: img.alpha.clone(),
             width: img.width,
             height: img.height,
         })
     }
 
+    /// The coordinates of each RGBXY vertex of `img` in terms of a palette of 4 or more colors,
+    /// and the index of the star color if the star triangulation was used.
+    fn hull_palette_matrix<C: PaletteColor>(
+        img: &ImageWeights,
+        palette: &[C],
+        options: &DecompositionOptions,
+    ) -> Result<(DMatrix<f64>, Option<usize>), RecolorError> {
+        let (palette_points, palette_ch, redundant) = palette_hull(palette, img.color_space)?;
+        if !redundant.is_empty() {
+            match options.redundant_colors {
+                RedundantColorHandling::Error => return Err(RecolorError::RedundantColors),
+                RedundantColorHandling::Drop => (),
+                RedundantColorHandling::Triangulate => {
+                    let matrix = crate::palette::compute_delaunay_coordinates(
+                        &palette_points,
+                        &palette_ch,
+                        &img.ch_rgb_vertices[..],
+                    )?;
+                    return Ok((matrix, None))
+                },
+            }
+        }
+
+        // Decompose to the colors that are vertices of the hull, and then give any redundant colors
+        // empty channels.
+        let kept: Vec<usize> = (0..palette_points.len())
+            .filter(|i| !redundant.contains(i))
+            .collect();
+        let kept_points: Vec<_> = kept.iter().map(|&i| palette_points[i]).collect();
+        let star_matrix = |star: usize| crate::palette::compute_star_triangulation_coordinates(
+            &kept_points,
+            &palette_ch,
+            star,
+            &img.ch_rgb_vertices[..],
+        );
+        let nearest = |target: [f64; 3]| {
+            let target = Vector3::from(img.color_space.from_srgb(target)) * 255.0;
+            let star = crate::palette::nearest_color(&kept_points, &target);
+            Ok::<_, RecolorError>((star, star_matrix(star)?))
+        };
+        let (star, kept_matrix) = match options.star_vertex {
+            StarVertex::NearestBlack => nearest([0.0; 3])?,
+            StarVertex::NearestWhite => nearest([1.0; 3])?,
+            StarVertex::Index(index) => {
+                let star = kept.iter()
+                    .position(|&i| i == index)
+                    .ok_or(RecolorError::InvalidStarVertex(index))?;
+                (star, star_matrix(star)?)
+            },
+            StarVertex::MinimizeSparsity => {
+                // Estimate the number of nonzero layer values each star produces by weighing each
+                // RGBXY vertex by how much the pixels use it.
+                let mut vertex_usage = vec![0.0; img.ch_rgb_vertices.len()];
+                for (_, vertex, weight) in img.weights.triplet_iter() {
+                    vertex_usage[vertex] += weight.abs();
+                }
+                let mut best: Option<(usize, DMatrix<f64>, f64)> = None;
+                for star in 0..kept_points.len() {
+                    let matrix = star_matrix(star)?;
+                    let nonzeros: f64 = matrix.row_iter()
+                        .zip(&vertex_usage)
+                        .map(|(row, usage)| {
+                            usage * row.iter().filter(|w| w.abs() > 1e-9).count() as f64
+                        })
+                        .sum();
+                    if best.as_ref().is_none_or(|(_, _, fewest)| nonzeros < *fewest) {
+                        best = Some((star, matrix, nonzeros));
+                    }
+                }
+                let (star, matrix, _) = best.expect("the palette has at least 4 colors");
+                (star, matrix)
+            },
+        };
+
+        if redundant.is_empty() {
+            return Ok((kept_matrix, Some(star)))
+        }
+        let mut matrix = DMatrix::zeros(kept_matrix.nrows(), palette_points.len());
+        for (column, &i) in kept.iter().enumerate() {
+            matrix.set_column(i, &kept_matrix.column(column));
+        }
+        Ok((matrix, Some(kept[star])))
+    }
+
     /// The number of channels in the palette that was used to create this decomposition.
     pub fn num_channels(&self) -> usize {
         self.matrix.ncols()
     }
 
+    /// The palette that was used to create this decomposition.
+    pub fn palette<C: PaletteColor>(&self) -> Vec<C> {
+        self.palette.iter().map(|c| C::from_rgb(*c)).collect()
+    }
+
+    /// The color space the decomposition was computed in. Reconstruction palettes are blended in
+    /// this color space.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// The index of the palette color that was used as the star vertex of the star triangulation.
+    ///
+    /// This is `None` if the decomposition didn't use a star triangulation, ie for palettes of 2
+    /// or 3 colors and with `RedundantColorHandling::Triangulate`.
+    pub fn star_vertex(&self) -> Option<usize> {
+        self.star
+    }
+
     /// The width of the original image
     pub fn width(&self) -> u32 {
         self.width
@@ -202,6 +730,11 @@ impl DecomposedImage {
         self.height
     }
 
+    /// Returns `true` if the original image had an alpha channel.
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
     /// Get the nth channel of the decomposed image as a grayscale image.
     pub fn get_channel_grayscale(&self, n: usize) -> Option<ImageBuffer<Luma<u8>, Vec<u8>>> {
         if n >= self.matrix.ncols() {
@@ -218,32 +751,471 @@ impl DecomposedImage {
 
     /// Rebuild a recolored image from the new palette.
     ///
-    /// Returns None if the provided palette is not the same size as the palette used to build the
-    /// decomposed image.
+    /// Returns an error if the provided palette is not the same size as the palette used to build
+    /// the decomposed image.
     ///
     /// Compared to creating the image weights and the decomposed image, this is a significantly
     /// cheaper operation.
-    pub fn reconstruct(&self, palette: &[Rgb<u8>]) -> Option<ImageBuffer<Rgb<u8>, Vec<u8>>>{
-        if palette.len() != self.num_channels() {
-            return None
+    pub fn reconstruct<C: PaletteColor>(&self, palette: &[C])
+        -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>, RecolorError>
+    {
+        let res = self.reconstruct_matrix(palette)?;
+
+        let mut img = ImageBuffer::from_pixel(self.width, self.height, Rgb([0, 0, 0]));
+        for (i, row) in res.row_iter().enumerate() {
+            let x = i % self.width as usize;
+            let y = i / self.width as usize;
+            img.put_pixel(x as u32, y as u32, Rgb([
+                (row[0] * 255.0).clamp(0.0, 255.0) as u8,
+                (row[1] * 255.0).clamp(0.0, 255.0) as u8,
+                (row[2] * 255.0).clamp(0.0, 255.0) as u8,
+            ]));
         }
-        let palette_matrix = Matrix::<f64, Dyn, Const<3>, _>::from_row_iterator(
-            palette.len(),
-            palette.iter().flat_map(|p| [p[0] as f64, p[1] as f64, p[2] as f64])
-        );
-        let res = &self.matrix * palette_matrix;
+        Ok(img)
+    }
+
+    /// Rebuild a recolored image from the new palette as a 16-bit image.
+    ///
+    /// Use this together with a 16-bit (or floating point) palette to avoid quantizing the result
+    /// to 8 bits.
+    ///
+    /// Returns an error if the provided palette is not the same size as the palette used to build
+    /// the decomposed image.
+    pub fn reconstruct_16bit<C: PaletteColor>(&self, palette: &[C])
+        -> Result<ImageBuffer<Rgb<u16>, Vec<u16>>, RecolorError>
+    {
+        let res = self.reconstruct_matrix(palette)?;
 
         let mut img = ImageBuffer::from_pixel(self.width, self.height, Rgb([0, 0, 0]));
         for (i, row) in res.row_iter().enumerate() {
             let x = i % self.width as usize;
             let y = i / self.width as usize;
             img.put_pixel(x as u32, y as u32, Rgb([
-                row[0].clamp(0.0, 255.0) as u8,
-                row[1].clamp(0.0, 255.0) as u8,
-                row[2].clamp(0.0, 255.0) as u8,
+                (row[0] * 65535.0).round().clamp(0.0, 65535.0) as u16,
+                (row[1] * 65535.0).round().clamp(0.0, 65535.0) as u16,
+                (row[2] * 65535.0).round().clamp(0.0, 65535.0) as u16,
             ]));
         }
-        Some(img)
+        Ok(img)
+    }
+
+    /// Rebuild a recolored image from the new palette as a floating point image.
+    ///
+    /// Unlike the other reconstruction methods, the output is not clamped to the range 0-1. This
+    /// makes it suitable for HDR and linear workflows.
+    ///
+    /// Returns an error if the provided palette is not the same size as the palette used to build
+    /// the decomposed image.
+    pub fn reconstruct_f32<C: PaletteColor>(&self, palette: &[C])
+        -> Result<ImageBuffer<Rgb<f32>, Vec<f32>>, RecolorError>
+    {
+        let res = self.reconstruct_matrix(palette)?;
+
+        let mut img = ImageBuffer::from_pixel(self.width, self.height, Rgb([0.0, 0.0, 0.0]));
+        for (i, row) in res.row_iter().enumerate() {
+            let x = i % self.width as usize;
+            let y = i / self.width as usize;
+            img.put_pixel(x as u32, y as u32, Rgb([row[0] as f32, row[1] as f32, row[2] as f32]));
+        }
+        Ok(img)
+    }
+
+    /// Rebuild a recolored image from the new palette, including the alpha channel of the original
+    /// image.
+    ///
+    /// If the original image didn't have an alpha channel, every pixel is fully opaque.
+    ///
+    /// Returns an error if the provided palette is not the same size as the palette used to build
+    /// the decomposed image.
+    pub fn reconstruct_rgba<C: PaletteColor>(&self, palette: &[C])
+        -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, RecolorError>
+    {
+        let rgb = self.reconstruct(palette)?;
+        let mut img = ImageBuffer::from_pixel(self.width, self.height, Rgba([0, 0, 0, 255]));
+        for (i, (rgba, pix)) in img.pixels_mut().zip(rgb.pixels()).enumerate() {
+            let alpha = self.alpha.as_ref().map_or(1.0, |alpha| alpha[i]);
+            *rgba = Rgba([pix[0], pix[1], pix[2], (alpha * 255.0).round().clamp(0.0, 255.0) as u8]);
+        }
+        Ok(img)
+    }
+
+    /// Compute the recolored pixels as rows of RGB values in the range 0-1.
+    ///
+    /// The palette is blended in the color space of the decomposition, and the result is converted
+    /// back to sRGB.
+    pub(crate) fn reconstruct_matrix<C: PaletteColor>(&self, palette: &[C])
+        -> Result<OMatrix<f64, Dyn, Const<3>>, RecolorError>
+    {
+        if palette.len() != self.num_channels() {
+            return Err(RecolorError::PaletteSizeMismatch {
+                expected: self.num_channels(),
+                actual: palette.len(),
+            })
+        }
+        let palette_matrix = Matrix::<f64, Dyn, Const<3>, _>::from_row_iterator(
+            palette.len(),
+            palette.iter().flat_map(|p| self.color_space.from_srgb(p.to_rgb()))
+        );
+        let mut res = &self.matrix * palette_matrix;
+        if self.color_space != ColorSpace::Srgb {
+            for mut row in res.row_iter_mut() {
+                let rgb = self.color_space.to_srgb([row[0], row[1], row[2]]);
+                row.copy_from_slice(&rgb);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// The indices of the colors of `palette` that aren't vertices of the palette's 3D convex hull.
+///
+/// `DecomposedImage::new` rejects palettes with redundant colors. This can be used to find out
+/// which colors are the problem, or which colors `RedundantColorHandling::Drop` will drop.
+///
+/// The hull depends on the color space, so pass the color space of the `ImageWeights` the palette
+/// will be used with.
+///
+/// Returns an error if the palette has fewer than 4 colors or its colors don't span all 3
+/// dimensions.
+pub fn redundant_colors<C: PaletteColor>(palette: &[C], color_space: ColorSpace)
+    -> Result<Vec<usize>, RecolorError>
+{
+    palette_hull(palette, color_space).map(|(_, _, redundant)| redundant)
+}
+
+/// The colors of `palette` converted to `color_space` and scaled to the range 0-255.
+fn palette_in_color_space<C: PaletteColor>(palette: &[C], color_space: ColorSpace)
+    -> Vec<Vector3<f64>>
+{
+    palette.iter()
+        .map(|c| Vector3::from(color_space.from_srgb(c.to_rgb())) * 255.0)
+        .collect()
+}
+
+/// The colors of a palette, their convex hull, and the indices of the colors that aren't vertices
+/// of the hull.
+type PaletteHull = (Vec<Vector3<f64>>, ConvexHull<Const<3>>, Vec<usize>);
+
+/// The colors of `palette` converted to `color_space` and scaled to the range 0-255, their convex
+/// hull, and the indices of the colors that aren't vertices of the hull.
+fn palette_hull<C: PaletteColor>(palette: &[C], color_space: ColorSpace)
+    -> Result<PaletteHull, RecolorError>
+{
+    if palette.len() < 4 {
+        return Err(RecolorError::PaletteTooSmall { min: 4, actual: palette.len() })
     }
+    let palette_points = palette_in_color_space(palette, color_space);
+    if !is_full_dimensional(&palette_points) {
+        return Err(RecolorError::DegenerateHull("palette colors"))
+    }
+    let palette_ch = ConvexHull::<Const<3>>::try_from_vectors(&palette_points)?;
+    let redundant = palette_points.iter()
+        .enumerate()
+        .filter(|(_, color)| palette_ch.vertices().all(|v| v.point() != *color))
+        .map(|(i, _)| i)
+        .collect();
+    Ok((palette_points, palette_ch, redundant))
 }
 
+/// Returns `true` if the `points` aren't confined to a lower dimensional subspace (a line, a
+/// plane, etc.), ie if qhull will be able to build a convex hull out of them.
+pub(crate) fn is_full_dimensional<const D: usize>(points: &[SVector<f64, D>]) -> bool {
+    if points.len() <= D {
+        return false
+    }
+
+    // The points span all D dimensions exactly when their covariance matrix is full rank.
+    let n = points.len() as f64;
+    let mut centroid = DVector::<f64>::zeros(D);
+    for p in points {
+        for (c, x) in centroid.iter_mut().zip(p.iter()) {
+            *c += x / n;
+        }
+    }
+    let mut covariance = DMatrix::<f64>::zeros(D, D);
+    let mut diff = DVector::<f64>::zeros(D);
+    for p in points {
+        for ((d, x), c) in diff.iter_mut().zip(p.iter()).zip(centroid.iter()) {
+            *d = x - c;
+        }
+        covariance.ger(1.0, &diff, &diff, 1.0);
+    }
+
+    let singular_values = covariance.singular_values();
+    let largest = singular_values.max();
+    largest > 0.0 && singular_values.iter().all(|s| *s > largest * 1e-12)
+}
+
+#[test]
+fn test_reconstruct_rgba_preserves_alpha() {
+    let img = ImageBuffer::from_fn(8, 8, |x, y| {
+        Rgba([
+            (x * 29 + y * y) as u8,
+            (y * 25 + x * x) as u8,
+            ((x * y * 7 + 13) % 256) as u8,
+            (x * y * 4) as u8,
+        ])
+    });
+    let weights = ImageWeights::new(&img).unwrap();
+    assert!(weights.has_alpha());
+
+    let palette: [Rgb<u8>; 4] = [
+        Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255]),
+    ];
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+    let reconstructed = decomposed.reconstruct_rgba(&palette).unwrap();
+    for (x, y, pix) in reconstructed.enumerate_pixels() {
+        assert_eq!(pix[3], img.get_pixel(x, y)[3]);
+    }
+}
+
+#[test]
+fn test_reconstruct_16bit() {
+    let img = ImageBuffer::from_fn(8, 8, |x, y| {
+        Rgb([
+            (x * 4099 + y * y * 613) as u16,
+            (y * 4111 + x * x * 617) as u16,
+            ((x * y * 1021 + 13) % 65536) as u16,
+        ])
+    });
+    let weights = ImageWeights::new(&img).unwrap();
+
+    // Every pixel lies within the RGB cube, so using its corners as the palette should give back
+    // the original image.
+    let palette = [
+        Rgb([0, 0, 0]), Rgb([65535, 0, 0]), Rgb([0, 65535, 0]), Rgb([0, 0, 65535]),
+        Rgb([65535, 65535, 0]), Rgb([65535, 0, 65535]), Rgb([0, 65535, 65535]),
+        Rgb([65535u16, 65535, 65535]),
+    ];
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+    let reconstructed = decomposed.reconstruct_16bit(&palette).unwrap();
+    for (x, y, pix) in reconstructed.enumerate_pixels() {
+        let original = img.get_pixel(x, y);
+        for c in 0..3 {
+            assert!((pix[c] as i32 - original[c] as i32).abs() <= 1);
+        }
+    }
+}
+
+#[test]
+fn test_solid_color_image_is_an_error() {
+    let img = ImageBuffer::from_pixel(8, 8, Rgb([40u8, 80, 120]));
+    assert!(matches!(ImageWeights::new(&img), Err(RecolorError::DegenerateHull(_))));
+}
+
+#[test]
+fn test_reconstruct_palette_size_mismatch() {
+    let img = crate::test_util::test_image();
+    let weights = ImageWeights::new(&img).unwrap();
+    let palette: [Rgb<u8>; 4] = [
+        Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255]),
+    ];
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+    assert!(matches!(
+        decomposed.reconstruct(&palette[..3]),
+        Err(RecolorError::PaletteSizeMismatch { expected: 4, actual: 3 })
+    ));
+}
+
+#[test]
+fn test_redundant_colors() {
+    let img = crate::test_util::test_image();
+    let weights = ImageWeights::new(&img).unwrap();
+    // Gray is inside the tetrahedron of the other colors.
+    let palette: [Rgb<u8>; 5] = [
+        Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255]), Rgb([60, 60, 60]),
+    ];
+    assert_eq!(redundant_colors(&palette, ColorSpace::Srgb).unwrap(), [4]);
+    assert!(matches!(
+        DecomposedImage::new(&weights, &palette),
+        Err(RecolorError::RedundantColors)
+    ));
+
+    let options = DecompositionOptions {
+        redundant_colors: RedundantColorHandling::Drop,
+        ..DecompositionOptions::default()
+    };
+    let decomposed = DecomposedImage::with_options(&weights, &palette, &options).unwrap();
+    assert_eq!(decomposed.num_channels(), 5);
+    assert!(decomposed.get_channel_grayscale(4).unwrap().pixels().all(|p| p[0] == 0));
+
+    // Every channel is used, and the weights still add up to one.
+    let options = DecompositionOptions {
+        redundant_colors: RedundantColorHandling::Triangulate,
+        ..DecompositionOptions::default()
+    };
+    let decomposed = DecomposedImage::with_options(&weights, &palette, &options).unwrap();
+    assert_eq!(decomposed.num_channels(), 5);
+    assert!(decomposed.get_channel_grayscale(4).unwrap().pixels().any(|p| p[0] > 0));
+    for row in decomposed.matrix.row_iter() {
+        assert!((row.sum() - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_two_tone_image() {
+    // Black line art on white, with gray antialiasing. The colors all lie on a line.
+    let img = ImageBuffer::from_fn(8, 8, |x, y| {
+        let v = ((x * y * 4) % 256) as u8;
+        Rgb([v, v, v])
+    });
+    let weights = ImageWeights::new(&img).unwrap();
+
+    let palette: [Rgb<u8>; 2] = [Rgb([0, 0, 0]), Rgb([255, 255, 255])];
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+    assert_eq!(decomposed.num_channels(), 2);
+    let reconstructed = decomposed.reconstruct(&palette).unwrap();
+    for (x, y, pix) in reconstructed.enumerate_pixels() {
+        assert!((pix[0] as i32 - img.get_pixel(x, y)[0] as i32).abs() <= 1);
+    }
+
+    // Recoloring to a duotone moves every pixel along the new segment.
+    let duotone: [Rgb<u8>; 2] = [Rgb([0, 0, 128]), Rgb([255, 255, 0])];
+    let recolored = decomposed.reconstruct(&duotone).unwrap();
+    assert_eq!(*recolored.get_pixel(0, 0), Rgb([0, 0, 128]));
+
+    // A palette of three colors on a line can't form a triangle.
+    let collinear: [Rgb<u8>; 3] = [Rgb([0, 0, 0]), Rgb([128, 128, 128]), Rgb([255, 255, 255])];
+    assert!(matches!(
+        DecomposedImage::new(&weights, &collinear),
+        Err(RecolorError::DegenerateHull(_))
+    ));
+    assert!(matches!(
+        DecomposedImage::new(&weights, &palette[..1]),
+        Err(RecolorError::PaletteTooSmall { min: 2, actual: 1 })
+    ));
+}
+
+#[test]
+fn test_star_vertex() {
+    let img = crate::test_util::test_image();
+    let weights = ImageWeights::new(&img).unwrap();
+    let palette = crate::test_util::cube_palette();
+    assert_eq!(DecomposedImage::new(&weights, &palette).unwrap().star_vertex(), Some(0));
+
+    for (star_vertex, expected) in [
+        (StarVertex::NearestWhite, Some(7)),
+        (StarVertex::Index(3), Some(3)),
+    ] {
+        let options = DecompositionOptions { star_vertex, ..DecompositionOptions::default() };
+        let decomposed = DecomposedImage::with_options(&weights, &palette, &options).unwrap();
+        assert_eq!(decomposed.star_vertex(), expected);
+        // The star doesn't change how well the palette reproduces the image.
+        let reconstructed = decomposed.reconstruct(&palette).unwrap();
+        for (x, y, pix) in reconstructed.enumerate_pixels() {
+            let original = img.get_pixel(x, y);
+            for c in 0..3 {
+                assert!((pix[c] as i32 - original[c] as i32).abs() <= 1);
+            }
+        }
+    }
+
+    let options = DecompositionOptions {
+        star_vertex: StarVertex::MinimizeSparsity,
+        ..DecompositionOptions::default()
+    };
+    let decomposed = DecomposedImage::with_options(&weights, &palette, &options).unwrap();
+    assert!(decomposed.star_vertex().is_some());
+
+    let options = DecompositionOptions {
+        star_vertex: StarVertex::Index(8),
+        ..DecompositionOptions::default()
+    };
+    assert!(matches!(
+        DecomposedImage::with_options(&weights, &palette, &options),
+        Err(RecolorError::InvalidStarVertex(8))
+    ));
+}
+
+#[test]
+fn test_spatial_weight() {
+    // A wide image, so the aspect ratio matters.
+    let img = ImageBuffer::from_fn(16, 4, |x, y| {
+        Rgb([(x * 15) as u8, (y * 61) as u8, ((x * y * 7 + 13) % 256) as u8])
+    });
+    let palette = crate::test_util::cube_palette();
+    for spatial_weight in [0.1, 1.0, 10.0] {
+        let options = ImageWeightsOptions { spatial_weight, ..ImageWeightsOptions::default() };
+        let weights = ImageWeights::with_options(&img, &options).unwrap();
+        assert_eq!(weights.spatial_weight(), spatial_weight);
+        let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+        let reconstructed = decomposed.reconstruct(&palette).unwrap();
+        for (x, y, pix) in reconstructed.enumerate_pixels() {
+            let original = img.get_pixel(x, y);
+            for c in 0..3 {
+                assert!((pix[c] as i32 - original[c] as i32).abs() <= 1);
+            }
+        }
+    }
+
+    let options = ImageWeightsOptions { spatial_weight: 0.0, ..ImageWeightsOptions::default() };
+    assert!(matches!(
+        ImageWeights::with_options(&img, &options),
+        Err(RecolorError::InvalidSpatialWeight(_))
+    ));
+}
+
+#[test]
+fn test_hull_sampling() {
+    // A smooth image, since downsampling an image with sharp details loses colors.
+    let img = ImageBuffer::from_fn(16, 16, |x, y| {
+        let (dx, dy) = (x as i32 - 8, y as i32 - 8);
+        Rgb([(x * 15) as u8, (y * 15) as u8, (dx * dx + dy * dy) as u8])
+    });
+    let palette = crate::test_util::cube_palette();
+    for hull_sampling in [HullSampling::Downsample(4), HullSampling::Deduplicate(4)] {
+        let options = ImageWeightsOptions { hull_sampling, ..ImageWeightsOptions::default() };
+        let weights = ImageWeights::with_options(&img, &options).unwrap();
+        let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+        // Every pixel gets weights that sum to one, even the ones outside of the smaller hull.
+        for row in decomposed.matrix.row_iter() {
+            assert!((row.sum() - 1.0).abs() < 1e-6);
+            assert!(row.iter().all(|w| *w > -1e-6));
+        }
+        // The weights are approximate, but the reconstruction stays close to the image.
+        let reconstructed = decomposed.reconstruct(&palette).unwrap();
+        let mut total_error = 0.0;
+        for (x, y, pix) in reconstructed.enumerate_pixels() {
+            let original = img.get_pixel(x, y);
+            for c in 0..3 {
+                total_error += (pix[c] as f64 - original[c] as f64).abs();
+            }
+        }
+        assert!(total_error / (16.0 * 16.0 * 3.0) < 8.0, "{hull_sampling:?}: {total_error}");
+    }
+}
+
+#[test]
+fn test_flat_image_weights() {
+    // Blocks of solid color, like UI art. Most pixels are inside a run of their own color.
+    let img = ImageBuffer::from_fn(32, 32, |x, y| {
+        let (bx, by) = (x / 8, y / 8);
+        Rgb([(bx * 80) as u8, (by * 80) as u8, (bx * by * 25) as u8])
+    });
+    let weights = ImageWeights::new(&img).unwrap();
+
+    // Leaving the interior pixels out of the hull doesn't change its vertices.
+    let options = ImageWeightsOptions {
+        hull_sampling: HullSampling::Downsample(1),
+        ..ImageWeightsOptions::default()
+    };
+    let all_pixels = ImageWeights::with_options(&img, &options).unwrap();
+    let sorted_vertices = |weights: &ImageWeights| {
+        let mut vertices: Vec<[f64; 3]> = weights.ch_rgb_vertices.iter()
+            .map(|v| [v.x, v.y, v.z])
+            .collect();
+        vertices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        vertices
+    };
+    assert_eq!(sorted_vertices(&weights), sorted_vertices(&all_pixels));
+
+    let palette = crate::test_util::cube_palette();
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+    let reconstructed = decomposed.reconstruct(&palette).unwrap();
+    for (x, y, pix) in reconstructed.enumerate_pixels() {
+        let original = img.get_pixel(x, y);
+        for c in 0..3 {
+            assert!((pix[c] as i32 - original[c] as i32).abs() <= 1);
+        }
+    }
+}
@@ -1,16 +1,79 @@
 use std::collections::HashMap;
 
-use good_lp::{ProblemVariables, VariableDefinition, SolverModel};
-use good_lp::solvers::Solution;
 use image::{GenericImageView, Rgb};
-use nalgebra::{Const, Vector3, DMatrix, Matrix4, Vector4};
+use nalgebra::{Const, Vector3, DMatrix, Matrix3, Matrix4, Vector4};
 use qhull_rs::{ConvexHull, Delaunay};
-use qhull_rs::convex_hull::{Vertex, Facet};
 
+use crate::{ColorSpace, PaletteColor, RecolorError, SourcePixel};
+use crate::collapse::EdgeCollapser;
 use crate::triangle_distance::triangle_distance_sqr;
 
 // Default error bound: 2.0/255.0
 
+/// How `compute_palette_with_options` measures the error of a candidate palette.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PaletteErrorMetric {
+    /// The root mean square distance from the pixels outside the palette's hull to the hull of the
+    /// clamped palette.
+    ///
+    /// This is what `compute_palette` uses. It is cheap, but it ignores pixels that are only
+    /// unrepresentable because of clamping, so it can underestimate the actual error.
+    #[default]
+    HullDistance,
+    /// The root mean square error of reconstructing each pixel with the final clamped and rounded
+    /// palette, using the same star triangulation as `DecomposedImage`.
+    ///
+    /// This is more expensive to compute, but it is the error that the palette will actually
+    /// produce (ignoring the error of the RGBXY weights, which doesn't depend on the palette).
+    Reconstruction,
+}
+
+/// Options that control how `compute_palette_with_options` simplifies a palette.
+///
+/// See `compute_palette` for what each of these do.
+#[derive(Clone, Debug)]
+pub struct PaletteOptions {
+    pub min_palette_size: usize,
+    pub max_palette_size: usize,
+    pub error_bound: f64,
+    pub error_metric: PaletteErrorMetric,
+    /// Colors that must be in the palette. These are added to the hull of the image and are never
+    /// moved or collapsed away.
+    ///
+    /// A locked color that is inside the hull of the image's colors can't be a vertex of the hull,
+    /// so this is an error.
+    pub locked_colors: Vec<Rgb<u8>>,
+    /// Keep every simplified color inside the RGB cube.
+    ///
+    /// By default, simplification can push colors outside of the RGB cube, which are then clamped
+    /// at the end. Clamping makes some pixels unrepresentable. With this set, the cube's bounds
+    /// are part of the problem solved for each collapse instead, so nothing is ever clamped. The
+    /// palette may end up with more colors as a result, since fewer edges can be collapsed.
+    ///
+    /// The sRGB gamut is only a cube in `ColorSpace::Srgb` and `ColorSpace::LinearSrgb`. Setting
+    /// this with any other color space is an error.
+    pub constrain_to_gamut: bool,
+    /// The color space to build the hull in. Use the same color space as the `ImageWeights` the
+    /// palette will be used with, since a palette that is a valid decomposition palette in one
+    /// color space can have redundant colors in another. `error_bound` is measured in this color
+    /// space as well.
+    pub color_space: ColorSpace,
+}
+
+impl Default for PaletteOptions {
+    fn default() -> Self {
+        PaletteOptions {
+            min_palette_size: 4,
+            max_palette_size: 10,
+            error_bound: 2.0 / 255.0,
+            error_metric: PaletteErrorMetric::default(),
+            locked_colors: vec![],
+            constrain_to_gamut: false,
+            color_space: ColorSpace::default(),
+        }
+    }
+}
+
 /// Compute a decomposition palette for an image.
 ///
 /// This method computes a palette for an image. The size of the palette is determined by
@@ -27,7 +90,10 @@ use crate::triangle_distance::triangle_distance_sqr;
 /// some pixels to become unrepresentable.
 ///
 /// Because the simplification process employees 3D polytopes, the smallest a palette can be is 4
-/// colors. (After all, the simplest a 3D polytope can be is a tetrahedron.)
+/// colors. (After all, the simplest a 3D polytope can be is a tetrahedron.) The exception is an
+/// image whose colors don't span all 3 dimensions, such as a grayscale or two-tone image. If its
+/// colors lie on a line or in a triangle, the palette is the 2 or 3 colors at its ends or corners,
+/// regardless of the palette size limits.
 ///
 /// The average error that `error_bound` is compared to is based on the minimum distance between
 /// pixels outside the polytope and the nearest facet. This is not a prefect representation of the
@@ -35,104 +101,394 @@ use crate::triangle_distance::triangle_distance_sqr;
 /// chose a conservative `error_bound`, or maybe even start with 0. Alternatively, you could set
 /// `error_bound` to a very large value and the `min_palette_size` to 4 and then increment
 /// `min_palette_size` until the reconstructed image has no visually detectable reconstruction
-/// errors.
-pub fn compute_palette(
-    img: &impl GenericImageView<Pixel = Rgb<u8>>,
+/// errors. `compute_palette_with_options` can instead compare `error_bound` to the actual
+/// reconstruction error, at the cost of being slower.
+///
+/// The error is measured after each simplification and the simplification is only kept if the
+/// error is within `error_bound`, so the palette is the smallest one found that stays within it.
+/// Version 0.2 made the check before each simplification instead, and so returned the first
+/// palette past the bound, one step smaller.
+///
+/// Fully transparent pixels are ignored, since their color doesn't contribute to the image.
+///
+/// Returns an error if the image is a solid color, or if its colors lie on a plane but not in a
+/// triangle.
+pub fn compute_palette<P>(
+    img: &impl GenericImageView<Pixel = P>,
     min_palette_size: usize,
     max_palette_size: usize,
     error_bound: f64,
-) -> Vec<Rgb<u8>>
+) -> Result<Vec<Rgb<u8>>, RecolorError>
+    where P: SourcePixel,
+{
+    let options = PaletteOptions {
+        min_palette_size,
+        max_palette_size,
+        error_bound,
+        ..PaletteOptions::default()
+    };
+    compute_palette_with_options(img, &options).map(|report| report.palette)
+}
+
+/// Why `compute_palette_with_options` stopped simplifying the palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteStopReason {
+    /// The palette reached `min_palette_size` colors.
+    MinPaletteSize,
+    /// Simplifying the palette any further would have exceeded `error_bound`.
+    ErrorBound,
+    /// None of the edges of the palette's hull could be collapsed.
+    NoCollapsibleEdge,
+    /// Collapsing an edge didn't reduce the number of colors in the palette.
+    HullDidNotShrink,
+    /// The image's colors lie on a line or in a triangle, so the palette is the 2 or 3 colors at
+    /// its ends or corners and was never simplified.
+    DegenerateColors,
+}
+
+/// One step of the palette simplification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaletteStep {
+    /// The number of colors in the palette at this step.
+    pub palette_size: usize,
+    /// The error of the palette at this step, as measured by the error metric. The error is only
+    /// computed once the palette has at most `max_palette_size` colors, so this is `None` before
+    /// then.
+    pub error: Option<f64>,
+}
+
+/// The result of `compute_palette_with_options`, along with the details of how it was reached.
+#[derive(Clone, Debug)]
+pub struct PaletteReport {
+    /// The palette, with every color clamped to the RGB cube.
+    pub palette: Vec<Rgb<u8>>,
+    /// The colors of the palette before they were clamped and rounded, converted back to sRGB
+    /// and in the same order as `palette`. These can be outside of the range 0-1.
+    pub vertices: Vec<[f64; 3]>,
+    /// The indices of the colors in `palette` that were outside of the RGB cube and had to be
+    /// clamped.
+    pub clamped: Vec<usize>,
+    /// The error of `palette`, as measured by the error metric.
+    pub error: f64,
+    /// Every palette that was considered, starting with the convex hull of the image. If
+    /// simplification stopped because of `error_bound`, the last step is the rejected palette that
+    /// exceeded it.
+    pub history: Vec<PaletteStep>,
+    /// Why simplification stopped.
+    pub stop_reason: PaletteStopReason,
+}
+
+/// Like `compute_palette`, but with a choice of error metric. Returns a report with the error of
+/// the palette, as measured by `options.error_metric`, and the history of the simplification.
+///
+/// With `PaletteErrorMetric::Reconstruction`, every candidate palette is scored by the error it
+/// would actually produce. Once the palette is down to `max_palette_size` colors, it is only
+/// simplified further while that error stays within `error_bound`.
+pub fn compute_palette_with_options<P>(
+    img: &impl GenericImageView<Pixel = P>,
+    options: &PaletteOptions,
+) -> Result<PaletteReport, RecolorError>
+    where P: SourcePixel,
+{
+    simplify_palette(img, options, |_, _| ())
+}
+
+/// A palette of one size from `compute_palette_ladder`.
+#[derive(Clone, Debug)]
+pub struct LadderPalette {
+    /// The palette, with every color clamped to the RGB cube.
+    pub palette: Vec<Rgb<u8>>,
+    /// The error of `palette`, as measured by the error metric.
+    pub error: f64,
+}
+
+/// Compute the palettes of every size between `options.min_palette_size` and
+/// `options.max_palette_size` in one pass.
+///
+/// `compute_palette` simplifies the palette one step at a time, so this returns the palette after
+/// every step once it is down to `max_palette_size` colors, ordered from the largest palette to the
+/// smallest. `options.error_bound` is ignored, so simplification always continues down to
+/// `min_palette_size` colors if it can. Collapsing an edge can remove more than one color, so some
+/// sizes may be skipped.
+pub fn compute_palette_ladder<P>(
+    img: &impl GenericImageView<Pixel = P>,
+    options: &PaletteOptions,
+) -> Result<Vec<LadderPalette>, RecolorError>
+    where P: SourcePixel,
+{
+    let options = PaletteOptions {
+        error_bound: f64::INFINITY,
+        ..options.clone()
+    };
+    let mut ladder: Vec<LadderPalette> = vec![];
+    simplify_palette(img, &options, |palette, error| {
+        // The error is only computed once the palette is small enough to be returned.
+        let Some(error) = error else {
+            return
+        };
+        if ladder.last().is_some_and(|last| last.palette.len() == palette.len()) {
+            // The hull failed to shrink, so keep the latest palette of this size.
+            ladder.pop();
+        }
+        ladder.push(LadderPalette { palette, error });
+    })?;
+    Ok(ladder)
+}
+
+/// The simplification behind `compute_palette_with_options` and `compute_palette_ladder`.
+///
+/// `on_accept` is called with the palette of every hull the simplification accepts (starting with
+/// the hull of the image) and its error, if the error was computed.
+fn simplify_palette<P>(
+    img: &impl GenericImageView<Pixel = P>,
+    options: &PaletteOptions,
+    mut on_accept: impl FnMut(Vec<Rgb<u8>>, Option<f64>),
+) -> Result<PaletteReport, RecolorError>
+    where P: SourcePixel,
 {
     // The minimum palette size is 4 because that is the number of vertices of a tetrahedron.
-    let min_palette_size = std::cmp::max(min_palette_size, 4);
-
-    let mut ch: ConvexHull<Const<3>> = img.pixels()
-        .map(|(_, _, pix)| [
-            pix[0] as f64 / 255.0,
-            pix[1] as f64 / 255.0,
-            pix[2] as f64 / 255.0,
-        ].into())
+    let min_palette_size = std::cmp::max(options.min_palette_size, 4);
+    let max_palette_size = options.max_palette_size;
+    let error_bound = options.error_bound;
+    // Everything from here on is in the color space, until the palette is converted back.
+    let color_space = options.color_space;
+    if options.constrain_to_gamut && !color_space.gamut_is_cube() {
+        return Err(RecolorError::GamutConstraintUnsupported(color_space))
+    }
+
+    let locked: Vec<Vector3<f64>> = options.locked_colors.iter()
+        .map(|c| Vector3::from(color_space.from_srgb(c.to_rgb())))
         .collect();
-    let mut previous_vcount = ch.vertices().len();
+    let missing_locked_color = |ch: &ConvexHull<Const<3>>| locked.iter()
+        .position(|color| ch.vertices().all(|v| v.point() != color));
 
-    // Build up the list of unique pixels and their counts
-    let mut pixel_map = HashMap::new();
-    for (_, _, pixel) in img.pixels() {
-        let count = pixel_map.entry(pixel).or_insert(0);
-        *count += 1
-    }
-    let pixel_counts = pixel_map.into_iter()
-        .map(|(pixel, count)| (
-            Vector3::new(
-                pixel[0] as f64 / 255.0,
-                pixel[1] as f64 / 255.0,
-                pixel[2] as f64 / 255.0,
-            ),
-            count as f64
-        ))
-        .collect::<Vec<_>>();
+    let pixel_counts: Vec<_> = color_counts(img).into_iter()
+        .map(|(pixel, count)| (Vector3::from(color_space.from_srgb(pixel.into())), count))
+        .collect();
     let total_count: f64 = pixel_counts.iter()
         .map(|(_, count)| *count)
         .sum();
 
-    while ch.vertices().len() > min_palette_size {
-        // TODO: We need to calculate the level of error we've created here. This gives us a better
-        //       idea of when we should exit the loop
-        let (new_vertex, vertices_to_remove) = locate_edge_to_collapse(&ch);
-        let new_hull = ch.vertices()
+    let points: Vec<Vector3<f64>> = pixel_counts.iter()
+        .map(|(pixel, _)| *pixel)
+        .chain(locked.iter().cloned())
+        .collect();
+    if !crate::is_full_dimensional(&points) {
+        // The hull can't be built, but the colors may still fit a segment or triangle, which
+        // reconstructs every pixel exactly.
+        let Some(vertices) = degenerate_palette(&points) else {
+            return Err(RecolorError::DegenerateHull("image colors"))
+        };
+        if let Some(index) = locked.iter().position(|color| !vertices.contains(color)) {
+            return Err(RecolorError::LockedColorInsideHull(options.locked_colors[index].0))
+        }
+        let vertices: Vec<[f64; 3]> = vertices.iter()
+            .map(|v| color_space.to_srgb([v.x, v.y, v.z]))
+            .collect();
+        let palette: Vec<Rgb<u8>> = vertices.iter().map(|v| Rgb::from_rgb(*v)).collect();
+        on_accept(palette.clone(), Some(0.0));
+        return Ok(PaletteReport {
+            vertices,
+            clamped: vec![],
+            error: 0.0,
+            history: vec![PaletteStep { palette_size: palette.len(), error: Some(0.0) }],
+            stop_reason: PaletteStopReason::DegenerateColors,
+            palette,
+        })
+    }
+    let mut ch = ConvexHull::<Const<3>>::try_from_vectors(&points)?;
+    if let Some(index) = missing_locked_color(&ch) {
+        return Err(RecolorError::LockedColorInsideHull(options.locked_colors[index].0))
+    }
+    let mut previous_vcount = ch.vertices().len();
+
+    let measure_error = |ch: &ConvexHull<Const<3>>| match options.error_metric {
+        PaletteErrorMetric::HullDistance => {
+            compute_pixel_error(ch, color_space, &pixel_counts, total_count)
+        },
+        PaletteErrorMetric::Reconstruction => {
+            compute_reconstruction_error(ch, color_space, &pixel_counts, total_count)
+        },
+    };
+    // Calculating the average error can be expensive, so only do it for the last 6 or so
+    // iterations
+    let compute_error = |ch: &ConvexHull<Const<3>>| -> Result<Option<f64>, RecolorError> {
+        if ch.vertices().len() > max_palette_size {
+            return Ok(None)
+        }
+        measure_error(ch).map(Some)
+    };
+
+    let mut collapser = EdgeCollapser::new(&locked, options.constrain_to_gamut);
+    let mut error = compute_error(&ch)?;
+    let mut history = vec![PaletteStep { palette_size: previous_vcount, error }];
+    on_accept(hull_palette(&ch, color_space), error);
+    let stop_reason = loop {
+        if ch.vertices().len() <= min_palette_size {
+            break PaletteStopReason::MinPaletteSize
+        }
+        let Some((new_vertex, vertices_to_remove)) = collapser.locate_edge_to_collapse(&ch) else {
+            // None of the edges could be collapsed, so this is as simple as the hull gets.
+            break PaletteStopReason::NoCollapsibleEdge
+        };
+        let new_points: Vec<_> = ch.vertices()
             .filter(|v| !vertices_to_remove.contains(&v.index()))
             .map(|v| v.point())
             .cloned()
             .chain(std::iter::once(new_vertex.into()))
             .collect();
+        let new_hull = ConvexHull::try_from_vectors(&new_points)?;
+        if missing_locked_color(&new_hull).is_some() {
+            // The new point swallowed a locked color, so try the next best edge instead.
+            collapser.reject_last();
+            continue
+        }
 
-        // Calculating the average error can be expensive, so only do it for the last 6 or so
-        // iterations
-        if ch.vertices().len() <= max_palette_size {
-            let error = compute_pixel_error(&ch, &pixel_counts, total_count);
-            if error > error_bound {
-                // We've reached or exceeded the error bound, so we are exiting here.
-                // We return the previous hull that still was inside the error bound.
-                break
-            }
+        let new_error = compute_error(&new_hull)?;
+        history.push(PaletteStep { palette_size: new_hull.vertices().len(), error: new_error });
+        if new_error.is_some_and(|e| e > error_bound) {
+            // We've reached or exceeded the error bound, so we are exiting here.
+            // We return the previous hull that still was inside the error bound.
+            break PaletteStopReason::ErrorBound
         }
         ch = new_hull;
+        error = new_error;
+        on_accept(hull_palette(&ch, color_space), error);
 
         let vcount = ch.vertices().len();
         if vcount == previous_vcount {
             // If we failed to actually shrink the hull, then we have to bail, unfortunately.
-            break
+            break PaletteStopReason::HullDidNotShrink
         }
         previous_vcount = vcount;
+    };
+
+    // The error is reported even if the palette is larger than `max_palette_size`.
+    let error = match error {
+        Some(error) => error,
+        None => measure_error(&ch)?,
+    };
+    let vertices: Vec<[f64; 3]> = ch.vertices()
+        .map(|v| color_space.to_srgb([v.point().x, v.point().y, v.point().z]))
+        .collect();
+    let palette = hull_palette(&ch, color_space);
+    let clamped = vertices.iter()
+        .enumerate()
+        // Allow for the rounding error of the LP, which can land points a hair outside the cube.
+        .filter(|(_, v)| v.iter().any(|c| *c < -1e-9 || *c > 1.0 + 1e-9))
+        .map(|(i, _)| i)
+        .collect();
+    Ok(PaletteReport {
+        palette,
+        vertices,
+        clamped,
+        error,
+        history,
+        stop_reason,
+    })
+}
+
+/// The unique colors of the visible pixels of `img` and how many pixels have each color.
+///
+/// Fully transparent pixels are left out, since their color doesn't contribute to the image.
+pub(crate) fn color_counts<P>(img: &impl GenericImageView<Pixel = P>) -> Vec<(Vector3<f64>, f64)>
+    where P: SourcePixel,
+{
+    // f64 isn't hashable, so we key the map on the bit patterns of the components instead.
+    let mut pixel_map = HashMap::new();
+    for (_, _, pixel) in img.pixels() {
+        if pixel.alpha() == Some(0.0) {
+            continue
+        }
+        let count = pixel_map.entry(pixel.rgb().map(f64::to_bits)).or_insert(0);
+        *count += 1
+    }
+    // The hull and the simplification depend on the order of the colors, so sort them rather than
+    // leave them in the map's random order.
+    let mut counts: Vec<_> = pixel_map.into_iter().collect();
+    counts.sort_unstable_by_key(|(pixel, _)| *pixel);
+    counts.into_iter()
+        .map(|(pixel, count)| (Vector3::from(pixel.map(f64::from_bits)), count as f64))
+        .collect()
+}
+
+/// The palette of an image whose colors don't span all 3 dimensions: the 2 ends of the line they
+/// lie on, or the 3 corners of the triangle they lie in.
+///
+/// Returns `None` for a solid color, or if the colors lie on a plane but their 2D hull isn't a
+/// triangle.
+fn degenerate_palette(points: &[Vector3<f64>]) -> Option<Vec<Vector3<f64>>> {
+    let centroid = points.iter().sum::<Vector3<f64>>() / points.len() as f64;
+    let covariance: Matrix3<f64> = points.iter()
+        .map(|p| (p - centroid) * (p - centroid).transpose())
+        .sum();
+    // The directions the colors vary in, using the same tolerance as `is_full_dimensional`.
+    let eigen = covariance.symmetric_eigen();
+    let largest = eigen.eigenvalues.max();
+    let axes: Vec<Vector3<f64>> = (0..3)
+        .filter(|&i| largest > 0.0 && eigen.eigenvalues[i] > largest * 1e-12)
+        .map(|i| eigen.eigenvectors.column(i).into_owned())
+        .collect();
+
+    match axes[..] {
+        [axis] => {
+            let project = |p: &&Vector3<f64>| (*p - centroid).dot(&axis);
+            let start = points.iter().min_by(|l, r| project(l).total_cmp(&project(r)))?;
+            let end = points.iter().max_by(|l, r| project(l).total_cmp(&project(r)))?;
+            Some(vec![*start, *end])
+        },
+        [u, v] => {
+            let projected: Vec<[f64; 2]> = points.iter()
+                .map(|p| [(p - centroid).dot(&u), (p - centroid).dot(&v)])
+                .collect();
+            let ch = ConvexHull::<Const<2>>::try_from_arrays(&projected).ok()?;
+            if ch.vertices().len() != 3 {
+                return None
+            }
+            Some(ch.vertices()
+                .map(|vertex| {
+                    let index = projected.iter()
+                        .position(|p| p[..] == vertex.point().as_slice()[..])
+                        .unwrap();
+                    points[index]
+                })
+                .collect())
+        },
+        _ => None,
     }
+}
 
+/// The colors of the hull's vertices, converted to sRGB and clamped to the RGB cube.
+fn hull_palette(ch: &ConvexHull<Const<3>>, color_space: ColorSpace) -> Vec<Rgb<u8>> {
     ch.vertices()
-        .map(|v| v.point())
-        .map(|p| Rgb([
-            (p[0].clamp(0.0, 1.0) * 255.0).round() as u8,
-            (p[1].clamp(0.0, 1.0) * 255.0).round() as u8,
-            (p[2].clamp(0.0, 1.0) * 255.0).round() as u8,
-        ]))
+        .map(|v| Rgb::from_rgb(color_space.to_srgb([v.point().x, v.point().y, v.point().z])))
         .collect()
 }
 
+/// Clamp a hull vertex into the sRGB gamut, staying in the color space.
+fn clamp_color(p: &Vector3<f64>, color_space: ColorSpace) -> [f64; 3] {
+    let [r, g, b] = color_space.to_srgb([p.x, p.y, p.z]);
+    color_space.from_srgb([r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)])
+}
+
 // TODO: Something about how I'm computing the error is wrong or at least doesn't capture the
 //       actual reproduction error. :\
 fn compute_pixel_error(
     ch: &ConvexHull<Const<3>>,
+    color_space: ColorSpace,
     pixel_counts: &[(Vector3<f64>, f64)],
     total_count: f64,
-) -> f64 {
-    let new_ch: ConvexHull<Const<3>> = ch.vertices()
-        .map(|v| v.point())
-        .map(|p| [p.x.clamp(0.0, 1.0), p.y.clamp(0.0, 1.0), p.z.clamp(0.0, 1.0)].into())
-        .collect();
-    let tri = Delaunay::from_arrays(ch.vertices()
+) -> Result<f64, RecolorError> {
+    let new_ch = ConvexHull::<Const<3>>::try_from_arrays(&ch.vertices()
+        .map(|v| clamp_color(v.point(), color_space))
+        .collect::<Vec<[f64; 3]>>()
+    )?;
+    let tri = Delaunay::try_from_arrays(ch.vertices()
         .map(|v| v.point())
         .map(|p| [p.x, p.y, p.z])
         .collect::<Vec<[f64; 3]>>()
         .as_slice()
-    );
+    )?;
     let mut searcher = tri.simplex_searcher();
     // TODO: Finely tune this value. Too tight of a tolerance will report false positives (errors)
     //       while too loose will report false negatives.
@@ -159,184 +515,91 @@ fn compute_pixel_error(
 
     // We want the average of the square distance, so divide by the total number of pixels. This
     // way pixels inside the hull pull the error towards 0
-    (error / total_count).sqrt()
-}
-
-
-
-
-// We're going to simply the convex hull/palette by colapsing edges. It isn't easy to extract edge
-// data from qhull, so we'll compute it here in a slightly crude way.
-struct EdgeData<'a> {
-    edges: Box<[([Vertex<'a, Const<3>>; 2], Box<[Facet<'a, Const<3>>]>)]>,
+    Ok((error / total_count).sqrt())
 }
 
-impl<'a> EdgeData<'a> {
-    fn new(ch: &'a ConvexHull<Const<3>>) -> Self {
-        // TODO: Fuck, we need to build a different kind of hash map too :\
-        let mut faces_for_vertex = HashMap::new();
-        let mut edges = HashMap::new();
-        for facet in ch.facets() {
-            let mut it = facet.vertices();
-            let a = it.next().unwrap().clone();
-            let b = it.next().unwrap().clone();
-            let c = it.next().unwrap().clone();
-            for v in &[a, b, c] {
-                faces_for_vertex.entry(v.clone())
-                    .or_insert(vec![])
-                    .push(facet.clone())
-            }
-            for (start, end) in &[(a, b), (b, c), (c, a)] {
-                let (start, end) = if start.index() > end.index() {
-                    (end, start)
-                } else {
-                    (start, end)
-                };
-                edges.entry([*start, *end])
-                    .or_insert(vec![]);
-                // faces.push(facet)
-            }
-        }
-        for ([start, end], faces) in edges.iter_mut() {
-            let start_faces = &faces_for_vertex[start];
-            let end_faces = &faces_for_vertex[end];
-
-            // The 2 points should have only 2 faces in common.
-            faces.reserve(start_faces.len() + end_faces.len());
-            faces.extend(start_faces);
-            faces.extend(end_faces);
-            faces.sort_by_key(|f: &Facet<Const<3>>| f.index());
-            faces.dedup_by_key(|f| f.index());
-        }
-        let mut edges = edges.into_iter()
-            .map(|(key, value)| (key, value.into_boxed_slice()))
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-        edges.sort_by_key(|(verts, _)| (verts[0].index(), verts[1].index()));
-        EdgeData {
-            edges,
-        }
-    }
-
-    fn iter<'s>(&'s self)
-        -> impl ExactSizeIterator<Item = ([Vertex<'a, Const<3>>; 2], &'s [Facet<'a, Const<3>>])>
-    {
-        self.edges
-            .iter()
-            .map(|(vs, fs)| (*vs, &fs[..]))
+/// The root mean square error of reconstructing the pixels with the palette `ch` would produce.
+///
+/// The palette is clamped and rounded exactly like `compute_palette` does, and each pixel is then
+/// reconstructed from its star triangulation coordinates, the same as `DecomposedImage` does.
+fn compute_reconstruction_error(
+    ch: &ConvexHull<Const<3>>,
+    color_space: ColorSpace,
+    pixel_counts: &[(Vector3<f64>, f64)],
+    total_count: f64,
+) -> Result<f64, RecolorError> {
+    let palette: Vec<Vector3<f64>> = hull_palette(ch, color_space).iter()
+        .map(|c| Vector3::from(color_space.from_srgb(c.to_rgb())))
+        .collect();
+    if !crate::is_full_dimensional(&palette) {
+        // Clamping flattened the palette, so it can't reproduce most of the image.
+        return Ok(f64::INFINITY)
     }
-}
-
-
-fn locate_edge_to_collapse(ch: &ConvexHull<Const<3>>) -> ([f64; 3], [usize; 2]) {
-
-    let edge_data = EdgeData::new(ch);
-
-    let mut edge_candidates = Vec::with_capacity(edge_data.edges.len());
-    for (edge, faces) in edge_data.iter() {
+    let palette_ch = ConvexHull::<Const<3>>::try_from_vectors(&palette)?;
 
+    let pixels: Vec<Vector3<f64>> = pixel_counts.iter().map(|(pixel, _)| *pixel).collect();
+    // The star has to be a vertex of the hull, and clamping can leave the color nearest to black
+    // inside it, so only the vertices are candidates.
+    let star = palette_ch.vertices()
+        .filter_map(|v| palette.iter().position(|c| c == v.point()))
+        .min_by(|&l, &r| palette[l].norm().total_cmp(&palette[r].norm()))
+        .expect("a full-dimensional hull has vertices");
+    let coordinates = compute_star_triangulation_coordinates(&palette, &palette_ch, star, &pixels)?;
 
-        // Look at the all of the faces to which these 2 vertices are incident. We want to locate a
-        // point that will result in positive volume for all of the tetrahedrons formed by each
-        // face and the new point. This will ensure that we strictly increase volume when we
-        // collapse the edge to this point.
-        let mut face_points = Vec::with_capacity(faces.len());
-        let mut a = vec![];
-        let mut b = vec![];
-        let mut c = Vector3::new(0.0, 0.0, 0.0);
-        for face in faces {
-            // XXX This only works because we ensure a consistent ordering of the vertices of the
-            //     facets 3d hulls relative to their normals. QHull does not natively do that for
-            //     us :\
-            let mut verts = face.vertices();
-            let p0 = verts.next().unwrap().point();
-            let p1 = verts.next().unwrap().point();
-            let p2 = verts.next().unwrap().point();
-
-            face_points.push((p0, p1, p2));
-
-            let mut n = (p1 - p0).cross(&(p2 - p0));
-            n.normalize_mut();
-
-            a.push(n);
-            b.push(n.dot(&p0));
-            c += n;
-        }
-        for v in &mut a {
-            *v *= -1.0;
-        }
-        for v in &mut b {
-            *v *= -1.0;
-        }
-
-        let mut vars = ProblemVariables::new();
-        let px = vars.add(VariableDefinition::new().name("px"));
-        let py = vars.add(VariableDefinition::new().name("py"));
-        let pz = vars.add(VariableDefinition::new().name("pz"));
-        // Asshole Brits misspelling minimize
-        let mut model = vars.minimise(px * c[0] + py * c[1] + pz * c[2])
-            .using(good_lp::default_solver);
-
-        for (coefs, bound) in a.iter().zip(&b) {
-            model.add_constraint((px * coefs[0] + py * coefs[1] + pz * coefs[2]).leq(*bound));
-        }
-        match model.solve() {
-            Ok(solution) => {
-                let p = [solution.value(px), solution.value(py), solution.value(pz)];
-                let vol = face_points.iter()
-                    .map(|(p0, p1, p2)| tetrahedron_volume(p0, p1, p2, &p.into()))
-                    .sum::<f64>();
-                edge_candidates.push(([edge[0].index(), edge[1].index()], p, vol));
-            }
-            Err(_e) => (),
-        }
+    let mut error = 0.0;
+    for ((pixel, count), weights) in pixel_counts.iter().zip(coordinates.row_iter()) {
+        let reconstructed: Vector3<f64> = weights.iter()
+            .zip(&palette)
+            .map(|(weight, color)| color * *weight)
+            .sum();
+        error += (pixel - reconstructed).norm_squared() * *count;
     }
-    let (edge_vertices, new_point, _vol) = edge_candidates.iter()
-        .min_by(|(_, _, vol_a), (_, _, vol_b)| vol_a.partial_cmp(vol_b).unwrap())
-        .cloned()
-        .unwrap();
-
-    (new_point, edge_vertices)
+    Ok((error / total_count).sqrt())
 }
 
-fn tetrahedron_volume(a: &Vector3<f64>, b: &Vector3<f64>, c: &Vector3<f64>, d: &Vector3<f64>)
-    -> f64
-{
-    (a - d).dot(&(b - d).cross(&(c - d))).abs() / 6.0
-}
 
 
+
+/// Represent each of `img_rgb_values` in terms of the palette colors, using a "star"
+/// triangulation of the palette around the color at index `star`, which must be a vertex of
+/// `palette_ch`.
 pub(crate) fn compute_star_triangulation_coordinates(
-    palette: &[Rgb<u8>],
+    palette: &[Vector3<f64>],
     palette_ch: &ConvexHull<Const<3>>,
+    star: usize,
     img_rgb_values: &[Vector3<f64>],
-) -> DMatrix<f64>
+) -> Result<DMatrix<f64>, RecolorError>
 {
     // The order of the vertices of the convex hull will (probably) be different the order of the
     // colors in the palette, so we need to build a mapping from the hull vertex indices to the
     // palette indices.
     let palette_ch_vertex_map = palette_ch.vertices()
         .map(|v| v.point())
-        .map(|p| Rgb([p[0] as u8, p[1] as u8, p[2] as u8]))
-        .map(|p| palette.iter().position(|c| c == &p).unwrap())
+        .map(|p| palette.iter().position(|c| c == p).unwrap())
         .collect::<Vec<_>>();
 
-    let palette_size = palette_ch.vertices().count();
+    // Colors that appear more than once are only ever matched to their first occurrence, so the
+    // rest get empty columns.
+    let palette_size = palette.len();
 
-    // A "star" vertex that will be in every simplex of our triangulation. This should be the color
-    // closest to black (and ideally exactly black) to improve the quality of the decomposition.
+    // A "star" vertex that will be in every simplex of our triangulation. By default, this is the
+    // color closest to black (and ideally exactly black) to improve the quality of the
+    // decomposition.
     let (star_index, star_value) = palette_ch.vertices()
-        // Find the palette color closest to zero
-        .min_by(|vl, vr| vl.point().norm().total_cmp(&vr.point().norm()))
-        .map(|v| (v.index(), v.point().clone()))
-        .unwrap();
+        .find(|v| *v.point() == palette[star])
+        .map(|v| (v.index(), *v.point()))
+        .expect("the star is a vertex of the palette's hull");
 
     // Generate a "star" triangulation. The idea is that the tetrahedrons in the triangulation are
     // constructed using the triangles on the surface of the convex hull plus 1 point in the convex
     // hull - the "star". (Naturally, we exclude the triangles that include the star itself to
     // avoid degenerate tetrahedrons.)
+    //
+    // Triangles that aren't incident to the star can still be coplanar with it when qhull
+    // triangulates a merged facet. Those tetrahedrons are flat, so they don't cover any volume and
+    // can be skipped as well.
 
-    let solvers: Vec<_> = palette_ch.facets()
+    let (solvers, simplex_indices): (Vec<_>, Vec<_>) = palette_ch.facets()
         // Facets involving the star vertex would create degenerate tetrahedrons, so skip them.
         .filter(|f| f.vertices().all(|v| v.index() != star_index))
         .map(|f| {
@@ -355,18 +618,21 @@ pub(crate) fn compute_star_triangulation_coordinates(
                 col[2] = point[2];
                 // col[3] = 1.0;
             }
-            output
+            (f, output)
         })
         // XXX QR or LU? They both should work, I think, but I think QR can handle a greater range
         //     of matrices?
-        .map(|mat| mat.lu().try_inverse().unwrap())
-        .collect();
-    let simplex_indices: Vec<_> = palette_ch.facets()
-        .filter(|f| f.vertices().all(|v| v.index() != star_index))
-        .map(|f| Vector4::from_iterator(
+        .filter_map(|(f, mat)| {
+            let solver = mat.lu().try_inverse()?;
+            let indices = Vector4::from_iterator(
                 std::iter::once(star_index).chain(f.vertices().map(|v| v.index()))
-            ))
-        .collect();
+            );
+            Some((solver, indices))
+        })
+        .unzip();
+    if solvers.is_empty() {
+        return Err(RecolorError::DegenerateHull("palette colors"))
+    }
 
     // TODO: Should we be using a sparse matrix? Is it likely to matter?
     //       Maybe we could benchmark to get an idea of how it affects performance?
@@ -389,37 +655,18 @@ pub(crate) fn compute_star_triangulation_coordinates(
         } else {
             // If this point is outside of the convex hull, then we want to find the point on the
             // hull closest to the point.
-            let (closest_facet, projected_point, _) = palette_ch.facets()
-                .map(|f| {
-                    let mut it = f.vertices();
-                    let v0 = it.next().unwrap().point().clone();
-                    let v1 = it.next().unwrap().point().clone();
-                    let v2 = it.next().unwrap().point().clone();
-                    let projected_point = crate::triangle_distance::triangle_closest_point(
-                        &pixel, &v0, &v1, &v2,
-                    );
-                    let diff = pixel - projected_point;
-                    let dist = diff.dot(&diff);
-                    (f, projected_point, dist)
-                })
-                .min_by(|(_, _, left), (_, _, right)| left.total_cmp(right))
-                .unwrap();
-
-            let solver_index = simplex_indices.iter().enumerate()
-                .find(|(_, indices)| {
-                    // This is a match if the simplex has all 3 of the vertices of closest facet.
-                    // There should only every be 1 simplex for which that is true.
-                    let indices = indices.as_slice();
-                    closest_facet.vertices()
-                        .map(|v| v.index())
-                        .all(|i| indices.contains(&i))
-                })
-                .map(|(i, _)| i)
-                .unwrap();
+            let projected_point = closest_point_on_hull(palette_ch, pixel)?;
 
-            let solver = &solvers[solver_index];
+            // The projected point lies on the surface of the hull, so it is (up to rounding) in
+            // one of the tetrahedrons. The closest facet's own tetrahedron may have been skipped
+            // for being flat, so pick whichever tetrahedron the point is the least outside of.
             let vec = Vector4::new(projected_point[0], projected_point[1], projected_point[2], 1.0);
-            solver.mul_to(&vec, &mut bcoords);
+            let least_outside = |solver: &Matrix4<f64>| (solver * vec).min();
+            let solver_index = (0..solvers.len())
+                .max_by(|&l, &r| least_outside(&solvers[l]).total_cmp(&least_outside(&solvers[r])))
+                .unwrap_or(0);
+
+            solvers[solver_index].mul_to(&vec, &mut bcoords);
             &simplex_indices[solver_index]
         };
         let mut row = matrix.row_mut(row_number);
@@ -428,6 +675,299 @@ pub(crate) fn compute_star_triangulation_coordinates(
         }
     }
 
-    matrix
+    Ok(matrix)
 }
 
+/// The index of the color of `palette` closest to `target`.
+pub(crate) fn nearest_color(palette: &[Vector3<f64>], target: &Vector3<f64>) -> usize {
+    (0..palette.len())
+        .min_by(|&l, &r| (palette[l] - target).norm().total_cmp(&(palette[r] - target).norm()))
+        .expect("the palette isn't empty")
+}
+
+/// Like `compute_star_triangulation_coordinates`, but every color of the palette is a vertex of
+/// the triangulation, including the colors inside the hull of the palette.
+///
+/// The palette is triangulated with a Delaunay triangulation, and each pixel is represented by its
+/// barycentric coordinates in the tetrahedron that contains it. Pixels outside of the hull are
+/// projected onto it first.
+pub(crate) fn compute_delaunay_coordinates(
+    palette: &[Vector3<f64>],
+    palette_ch: &ConvexHull<Const<3>>,
+    img_rgb_values: &[Vector3<f64>],
+) -> Result<DMatrix<f64>, RecolorError>
+{
+    let tri = Delaunay::<Const<3>>::try_from_vectors(palette)?;
+    // As with the star triangulation, the triangulation's vertices need to be mapped back to the
+    // palette indices.
+    let tri_vertex_map = tri.vertices()
+        .map(|v| v.point())
+        .map(|p| palette.iter().position(|c| c == p).unwrap())
+        .collect::<Vec<_>>();
+
+    let mut matrix = DMatrix::from_element(img_rgb_values.len(), palette.len(), 0.0);
+    let mut simplex_searcher = tri.simplex_searcher();
+    let mut bcoords = Vector4::from_element(0.0);
+    const INITAL_TOLERANCE: f64 = 1e-10;
+    for (row_number, pixel) in img_rgb_values.iter().enumerate() {
+        simplex_searcher.set_eps(INITAL_TOLERANCE);
+        let mut simplex = simplex_searcher.find_simplex_mut(pixel, &mut bcoords);
+        if simplex.is_none() {
+            // The pixel is outside of the hull, so use the closest point on the hull instead. That
+            // point is (up to rounding) on the surface, so loosen the tolerance until it matches.
+            let projected_point = closest_point_on_hull(palette_ch, pixel)?;
+            while simplex.is_none() {
+                simplex = simplex_searcher.find_simplex_mut(&projected_point, &mut bcoords);
+                let current_tolerance = simplex_searcher.eps();
+                simplex_searcher.set_eps(current_tolerance * 2.0);
+            }
+        }
+        let mut row = matrix.row_mut(row_number);
+        for (vertex, value) in simplex.unwrap().vertices().zip(bcoords.iter()) {
+            row[tri_vertex_map[vertex.index()]] = *value;
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// The coordinates of `img_rgb_values` in terms of a palette of 2 or 3 colors, ie along the segment
+/// or within the triangle between the colors.
+///
+/// Points off the segment or triangle are represented by the closest point on it.
+pub(crate) fn compute_degenerate_coordinates(
+    palette: &[Vector3<f64>],
+    img_rgb_values: &[Vector3<f64>],
+) -> Result<DMatrix<f64>, RecolorError>
+{
+    let mut matrix = DMatrix::from_element(img_rgb_values.len(), palette.len(), 0.0);
+    match *palette {
+        [a, b] => {
+            let ab = b - a;
+            let length_sqr = ab.norm_squared();
+            if length_sqr == 0.0 {
+                return Err(RecolorError::DegenerateHull("palette colors"))
+            }
+            for (mut row, pixel) in matrix.row_iter_mut().zip(img_rgb_values) {
+                let t = ((pixel - a).dot(&ab) / length_sqr).clamp(0.0, 1.0);
+                row[0] = 1.0 - t;
+                row[1] = t;
+            }
+        },
+        [a, b, c] => {
+            let (ab, ac) = (b - a, c - a);
+            let (d00, d01, d11) = (ab.dot(&ab), ab.dot(&ac), ac.dot(&ac));
+            let denominator = d00 * d11 - d01 * d01;
+            // This is zero when the colors are collinear (or two of them are the same).
+            if denominator <= d00 * d11 * 1e-12 {
+                return Err(RecolorError::DegenerateHull("palette colors"))
+            }
+            for (mut row, pixel) in matrix.row_iter_mut().zip(img_rgb_values) {
+                let point = crate::triangle_distance::triangle_closest_point(pixel, &a, &b, &c);
+                let ap = point - a;
+                let (d20, d21) = (ap.dot(&ab), ap.dot(&ac));
+                let v = (d11 * d20 - d01 * d21) / denominator;
+                let w = (d00 * d21 - d01 * d20) / denominator;
+                row[0] = 1.0 - v - w;
+                row[1] = v;
+                row[2] = w;
+            }
+        },
+        _ => unreachable!("only palettes of 2 or 3 colors are decomposed without a hull"),
+    }
+    Ok(matrix)
+}
+
+/// The point on the surface of `ch` closest to `point`.
+fn closest_point_on_hull(ch: &ConvexHull<Const<3>>, point: &Vector3<f64>)
+    -> Result<Vector3<f64>, RecolorError>
+{
+    ch.facets()
+        .map(|f| {
+            let mut it = f.vertices();
+            let v0 = it.next().unwrap().point();
+            let v1 = it.next().unwrap().point();
+            let v2 = it.next().unwrap().point();
+            let projected_point = crate::triangle_distance::triangle_closest_point(
+                point, v0, v1, v2,
+            );
+            let diff = point - projected_point;
+            (projected_point, diff.dot(&diff))
+        })
+        .min_by(|(_, left), (_, right)| left.total_cmp(right))
+        .map(|(projected_point, _)| projected_point)
+        .ok_or(RecolorError::DegenerateHull("palette colors"))
+}
+
+
+#[test]
+fn test_reconstruction_error_metric() {
+    use image::ImageBuffer;
+
+    // The corners of the RGB cube. The cube can't be simplified without losing colors, so with no
+    // error allowed it must be kept as is.
+    let img = ImageBuffer::from_fn(4, 2, |x, y| {
+        let corner = x + 4 * y;
+        Rgb([
+            if corner & 1 != 0 { 255u8 } else { 0 },
+            if corner & 2 != 0 { 255 } else { 0 },
+            if corner & 4 != 0 { 255 } else { 0 },
+        ])
+    });
+    let options = PaletteOptions {
+        error_bound: 0.0,
+        error_metric: PaletteErrorMetric::Reconstruction,
+        ..PaletteOptions::default()
+    };
+    let report = compute_palette_with_options(&img, &options).unwrap();
+    assert_eq!(report.palette.len(), 8);
+    assert!(report.error < 1e-9);
+    assert_ne!(report.stop_reason, PaletteStopReason::MinPaletteSize);
+    assert!(report.clamped.is_empty());
+    assert_eq!(report.history[0].palette_size, 8);
+}
+
+#[test]
+fn test_palette_ladder() {
+    let img = crate::test_util::test_image();
+    let options = PaletteOptions {
+        max_palette_size: 8,
+        ..PaletteOptions::default()
+    };
+    let ladder = compute_palette_ladder(&img, &options).unwrap();
+    for step in &ladder {
+        assert!(step.palette.len() >= 4 && step.palette.len() <= 8);
+        assert!(step.error.is_finite());
+    }
+    for pair in ladder.windows(2) {
+        assert!(pair[0].palette.len() > pair[1].palette.len());
+    }
+
+    // The smallest palette of the ladder is the one compute_palette gives without an error bound.
+    let options = PaletteOptions {
+        error_bound: f64::INFINITY,
+        ..options
+    };
+    let report = compute_palette_with_options(&img, &options).unwrap();
+    if report.palette.len() <= 8 {
+        assert_eq!(ladder.last().unwrap().palette, report.palette);
+    }
+}
+
+#[test]
+fn test_locked_colors() {
+    let img = crate::test_util::test_image();
+    // White is a corner of the RGB cube, so it is always a vertex of the hull.
+    let options = PaletteOptions {
+        error_bound: f64::INFINITY,
+        locked_colors: vec![Rgb([255, 255, 255])],
+        ..PaletteOptions::default()
+    };
+    let report = compute_palette_with_options(&img, &options).unwrap();
+    assert!(report.palette.contains(&Rgb([255, 255, 255])));
+
+    // A color from the middle of the image can't be a vertex.
+    let options = PaletteOptions {
+        locked_colors: vec![*img.get_pixel(4, 4)],
+        ..options
+    };
+    assert!(matches!(
+        compute_palette_with_options(&img, &options),
+        Err(RecolorError::LockedColorInsideHull(_))
+    ));
+}
+
+#[test]
+fn test_constrain_to_gamut() {
+    use image::ImageBuffer;
+
+    let img = ImageBuffer::from_fn(8, 8, |x, y| {
+        Rgb([(x * 36) as u8, (y * 36) as u8, ((x * y * 7 + 13) % 256) as u8])
+    });
+    let options = PaletteOptions {
+        error_bound: f64::INFINITY,
+        constrain_to_gamut: true,
+        ..PaletteOptions::default()
+    };
+    let report = compute_palette_with_options(&img, &options).unwrap();
+    assert!(report.clamped.is_empty());
+
+    // The gamut isn't a cube in Oklab, so the constraint can't be applied there.
+    let options = PaletteOptions { color_space: ColorSpace::Oklab, ..options };
+    assert!(matches!(
+        compute_palette_with_options(&img, &options),
+        Err(RecolorError::GamutConstraintUnsupported(ColorSpace::Oklab))
+    ));
+}
+
+#[test]
+fn test_three_tone_palette() {
+    use image::ImageBuffer;
+
+    // Blends of three colors, so every color is in one triangle.
+    let img = ImageBuffer::from_fn(8, 8, |x, y| {
+        let (r, g) = if x + y < 8 { ((x * 30) as u8, (y * 30) as u8) } else { (0, 0) };
+        Rgb([r, g, 255 - r - g])
+    });
+    let report = compute_palette_with_options(&img, &PaletteOptions::default()).unwrap();
+    assert_eq!(report.stop_reason, PaletteStopReason::DegenerateColors);
+    assert_eq!(report.palette.len(), 3);
+    assert_eq!(report.error, 0.0);
+
+    let weights = crate::ImageWeights::new(&img).unwrap();
+    let decomposed = crate::DecomposedImage::new(&weights, &report.palette).unwrap();
+    let reconstructed = decomposed.reconstruct(&report.palette).unwrap();
+    for (x, y, pix) in reconstructed.enumerate_pixels() {
+        let original = img.get_pixel(x, y);
+        for c in 0..3 {
+            assert!((pix[c] as i32 - original[c] as i32).abs() <= 1);
+        }
+    }
+}
+
+#[test]
+fn test_palette_color_space() {
+    let img = crate::test_util::test_image();
+    for color_space in [ColorSpace::LinearSrgb, ColorSpace::Oklab, ColorSpace::Lab] {
+        let options = PaletteOptions {
+            error_bound: f64::INFINITY,
+            color_space,
+            ..PaletteOptions::default()
+        };
+        let report = compute_palette_with_options(&img, &options).unwrap();
+        assert!(report.palette.len() >= 4);
+
+        // The palette is meant for weights computed in the same color space.
+        let weights_options = crate::ImageWeightsOptions {
+            color_space,
+            ..crate::ImageWeightsOptions::default()
+        };
+        let weights = crate::ImageWeights::with_options(&img, &weights_options).unwrap();
+        let options = crate::DecompositionOptions {
+            redundant_colors: crate::RedundantColorHandling::Drop,
+            ..crate::DecompositionOptions::default()
+        };
+        let decomposed =
+            crate::DecomposedImage::with_options(&weights, &report.palette, &options).unwrap();
+        assert_eq!(decomposed.color_space(), color_space);
+        decomposed.reconstruct(&report.palette).unwrap();
+    }
+}
+
+#[test]
+fn test_compute_palette_error_bound() {
+    // Each simplification is measured before it is accepted, so the palette is the last one within
+    // the error bound. Going on to 6 colors would give an error of about 0.0087.
+    let img = crate::test_util::test_image();
+    let mut palette = compute_palette(&img, 4, 10, 0.005).unwrap();
+    palette.sort_by_key(|c| c.0);
+    assert_eq!(palette, [
+        Rgb([0, 0, 13]),
+        Rgb([43, 174, 13]),
+        Rgb([165, 191, 209]),
+        Rgb([202, 43, 13]),
+        Rgb([213, 143, 251]),
+        Rgb([235, 207, 0]),
+        Rgb([252, 224, 100]),
+    ]);
+}
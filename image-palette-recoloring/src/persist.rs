@@ -0,0 +1,486 @@
+use std::io::{self, Read, Write};
+
+use image::GenericImageView;
+use nalgebra::{DMatrix, Vector3};
+use nalgebra_sparse::CsrMatrix;
+
+use crate::{ColorSpace, DecomposedImage, ImageWeights, SourcePixel};
+
+// The on-disk format is a small header followed by the raw arrays that make up the data
+// structure. Everything is stored little-endian and every `usize` is widened to a `u64` so the
+// files can be moved between 32-bit (wasm) and 64-bit builds.
+//
+// ImageWeights (version 1):
+//   magic        [u8; 4]   b"IPRW"
+//   version      u32
+//   width        u32
+//   height       u32
+//   source_hash  u64       FNV-1a hash of the source image (see `hash_image`)
+//   nrows        u64
+//   ncols        u64
+//   nnz          u64
+//   row_offsets  [u64; nrows + 1]
+//   col_indices  [u64; nnz]
+//   values       [f64; nnz]
+//   nvertices    u64
+//   vertices     [[f64; 3]; nvertices]
+//   has_alpha    u8        0 or 1
+//   alpha        [f32; nrows]                       (only present if has_alpha is 1)
+//   color_space  u8        0 = sRGB, 1 = linear sRGB, 2 = OKLab, 3 = CIELAB
+//   spatial_weight f64
+//
+// DecomposedImage (version 1):
+//   magic        [u8; 4]   b"IPRD"
+//   version      u32
+//   width        u32
+//   height       u32
+//   nchannels    u32
+//   precision    u8        0 = f64, 1 = f32
+//   palette      [[f64; 3]; nchannels]             scaled to the range 0-1
+//   layers       [[f32 or f64; width * height]; nchannels]
+//   has_alpha    u8        0 or 1
+//   alpha        [f32; width * height]              (only present if has_alpha is 1)
+//   star         u64       index of the star color, or u64::MAX if there isn't one
+//   color_space  u8        same as for ImageWeights
+//
+// Files with any other version are rejected, so any change to either layout has to bump its
+// version.
+
+const IMAGE_WEIGHTS_MAGIC: &[u8; 4] = b"IPRW";
+const IMAGE_WEIGHTS_VERSION: u32 = 1;
+
+const DECOMPOSED_IMAGE_MAGIC: &[u8; 4] = b"IPRD";
+const DECOMPOSED_IMAGE_VERSION: u32 = 1;
+
+/// The precision used to store the layers of a `DecomposedImage` on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerPrecision {
+    /// Store the layers as `f32`s. This halves the file size and is more than enough precision to
+    /// reconstruct 8-bit images.
+    F32,
+    /// Store the layers exactly as they are kept in memory.
+    F64,
+}
+
+impl LayerPrecision {
+    fn tag(self) -> u8 {
+        match self {
+            LayerPrecision::F64 => 0,
+            LayerPrecision::F32 => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(LayerPrecision::F64),
+            1 => Some(LayerPrecision::F32),
+            _ => None,
+        }
+    }
+}
+
+impl ImageWeights {
+    /// Write the weights to `writer` so they can be reloaded later with `ImageWeights::load`.
+    ///
+    /// Since computing the weights is by far the most expensive part of recoloring an image, it
+    /// is worth caching them on disk if you plan on recoloring the same image across multiple runs
+    /// of your program.
+    ///
+    /// `img` must be the image the weights were computed from. A hash of its pixels is stored in
+    /// the file so that `ImageWeights::load_for_image` can tell when the image has changed.
+    /// Returns an error of kind `io::ErrorKind::InvalidInput` if its dimensions don't match.
+    pub fn save<P: SourcePixel>(
+        &self,
+        writer: &mut impl Write,
+        img: &impl GenericImageView<Pixel = P>,
+    ) -> io::Result<()> {
+        if img.dimensions() != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The image doesn't have the dimensions of the image weights",
+            ))
+        }
+        writer.write_all(IMAGE_WEIGHTS_MAGIC)?;
+        write_u32(writer, IMAGE_WEIGHTS_VERSION)?;
+        write_u32(writer, self.width)?;
+        write_u32(writer, self.height)?;
+        write_u64(writer, hash_image(img))?;
+
+        write_usize(writer, self.weights.nrows())?;
+        write_usize(writer, self.weights.ncols())?;
+        write_usize(writer, self.weights.nnz())?;
+        for offset in self.weights.row_offsets() {
+            write_usize(writer, *offset)?;
+        }
+        for index in self.weights.col_indices() {
+            write_usize(writer, *index)?;
+        }
+        for value in self.weights.values() {
+            write_f64(writer, *value)?;
+        }
+
+        write_usize(writer, self.ch_rgb_vertices.len())?;
+        for vertex in &self.ch_rgb_vertices {
+            for component in vertex.iter() {
+                write_f64(writer, *component)?;
+            }
+        }
+        write_alpha(writer, self.alpha.as_deref())?;
+        writer.write_all(&[self.color_space.tag()])?;
+        write_f64(writer, self.spatial_weight)?;
+        Ok(())
+    }
+
+    /// Read weights previously written by `ImageWeights::save`.
+    ///
+    /// This does not check that the weights belong to any particular image. Use
+    /// `ImageWeights::load_for_image` if you want stale data to be detected.
+    pub fn load(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != IMAGE_WEIGHTS_MAGIC {
+            return Err(invalid_data("Not an image weights file (bad magic number)"))
+        }
+        let version = read_u32(reader)?;
+        if version != IMAGE_WEIGHTS_VERSION {
+            return Err(invalid_data(format!("Unsupported image weights version {version}")))
+        }
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let source_hash = read_u64(reader)?;
+
+        let nrows = read_usize(reader)?;
+        let ncols = read_usize(reader)?;
+        let nnz = read_usize(reader)?;
+        if nrows != checked_mul(width as usize, height as usize)? {
+            return Err(invalid_data("Weight matrix row count doesn't match the image size"))
+        }
+        let num_offsets = nrows.checked_add(1).ok_or_else(|| invalid_data("Too many rows"))?;
+        let row_offsets = (0..num_offsets)
+            .map(|_| read_usize(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        let col_indices = (0..nnz)
+            .map(|_| read_usize(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        let values = (0..nnz)
+            .map(|_| read_f64(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        let weights = CsrMatrix::try_from_csr_data(nrows, ncols, row_offsets, col_indices, values)
+            .map_err(|e| invalid_data(e.to_string()))?;
+
+        let vertex_count = read_usize(reader)?;
+        if vertex_count != ncols {
+            return Err(invalid_data("Vertex count doesn't match the weight matrix column count"))
+        }
+        let ch_rgb_vertices = (0..vertex_count)
+            .map(|_| Ok(Vector3::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let alpha = read_alpha(reader, nrows)?;
+        let color_space = read_color_space(reader)?;
+        let spatial_weight = read_f64(reader)?;
+
+        Ok(ImageWeights {
+            weights,
+            ch_rgb_vertices,
+            color_space,
+            spatial_weight,
+            alpha,
+            width,
+            height,
+            source_hash: Some(source_hash),
+        })
+    }
+
+    /// Read weights previously written by `ImageWeights::save`, verifying that they were computed
+    /// from `img`.
+    ///
+    /// If the file was produced from a different image (or the image has since been modified),
+    /// this returns an error of kind `io::ErrorKind::InvalidData`.
+    pub fn load_for_image<P: SourcePixel>(
+        reader: &mut impl Read,
+        img: &impl GenericImageView<Pixel = P>,
+    ) -> io::Result<Self> {
+        let weights = Self::load(reader)?;
+        if !weights.is_for_image(img) {
+            return Err(invalid_data("The image weights were computed from a different image"))
+        }
+        Ok(weights)
+    }
+
+    /// Returns `true` if these weights were computed from `img`.
+    ///
+    /// For weights read with `ImageWeights::load`, this compares the dimensions and a hash of the
+    /// pixels of `img` against the values recorded by `ImageWeights::save`, so it is cheap
+    /// compared to recomputing the weights. Weights computed by this process don't carry a hash,
+    /// so only their dimensions are compared.
+    pub fn is_for_image<P: SourcePixel>(&self, img: &impl GenericImageView<Pixel = P>) -> bool {
+        img.dimensions() == (self.width, self.height)
+            && self.source_hash.is_none_or(|hash| hash == hash_image(img))
+    }
+}
+
+impl DecomposedImage {
+    /// Write the decomposition to `writer` so it can be reloaded later with
+    /// `DecomposedImage::load`.
+    ///
+    /// The file contains every layer as a dense array along with the decomposition palette, so
+    /// reloading it allows recoloring to continue without recomputing the image weights or the
+    /// decomposition itself.
+    pub fn save(&self, writer: &mut impl Write, precision: LayerPrecision) -> io::Result<()> {
+        writer.write_all(DECOMPOSED_IMAGE_MAGIC)?;
+        write_u32(writer, DECOMPOSED_IMAGE_VERSION)?;
+        write_u32(writer, self.width)?;
+        write_u32(writer, self.height)?;
+        write_u32(writer, self.num_channels() as u32)?;
+        writer.write_all(&[precision.tag()])?;
+        for color in &self.palette {
+            for component in color {
+                write_f64(writer, *component)?;
+            }
+        }
+        // nalgebra stores matrices in column-major order, so this writes the layers one after
+        // another.
+        for value in self.matrix.iter() {
+            match precision {
+                LayerPrecision::F32 => writer.write_all(&(*value as f32).to_le_bytes())?,
+                LayerPrecision::F64 => write_f64(writer, *value)?,
+            }
+        }
+        write_alpha(writer, self.alpha.as_deref())?;
+        write_u64(writer, self.star.map_or(u64::MAX, |star| star as u64))?;
+        writer.write_all(&[self.color_space.tag()])?;
+        Ok(())
+    }
+
+    /// Read a decomposition previously written by `DecomposedImage::save`.
+    pub fn load(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != DECOMPOSED_IMAGE_MAGIC {
+            return Err(invalid_data("Not a decomposed image file (bad magic number)"))
+        }
+        let version = read_u32(reader)?;
+        if version != DECOMPOSED_IMAGE_VERSION {
+            return Err(invalid_data(format!("Unsupported decomposed image version {version}")))
+        }
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let num_channels = read_u32(reader)? as usize;
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+        let precision = LayerPrecision::from_tag(tag[0])
+            .ok_or_else(|| invalid_data(format!("Unknown layer precision {}", tag[0])))?;
+
+        let palette = (0..num_channels)
+            .map(|_| Ok([read_f64(reader)?, read_f64(reader)?, read_f64(reader)?]))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let num_pixels = checked_mul(width as usize, height as usize)?;
+        let values = (0..checked_mul(num_pixels, num_channels)?)
+            .map(|_| match precision {
+                LayerPrecision::F32 => {
+                    let mut buf = [0u8; 4];
+                    reader.read_exact(&mut buf)?;
+                    Ok(f32::from_le_bytes(buf) as f64)
+                },
+                LayerPrecision::F64 => read_f64(reader),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let alpha = read_alpha(reader, num_pixels)?;
+        let star = match read_u64(reader)? {
+            u64::MAX => None,
+            star if (star as usize) < num_channels => Some(star as usize),
+            star => return Err(invalid_data(format!("Star {star} is not a palette color"))),
+        };
+        let color_space = read_color_space(reader)?;
+
+        Ok(DecomposedImage {
+            matrix: DMatrix::from_vec(num_pixels, num_channels, values),
+            palette,
+            star,
+            color_space,
+            alpha,
+            width,
+            height,
+        })
+    }
+}
+
+/// Hash the dimensions and pixels of an image.
+///
+/// This is a plain FNV-1a hash. We can't use `std::hash::DefaultHasher` because its output isn't
+/// guaranteed to be stable between Rust releases, which would invalidate every saved file.
+pub(crate) fn hash_image<P: SourcePixel>(img: &impl GenericImageView<Pixel = P>) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    feed(&img.width().to_le_bytes());
+    feed(&img.height().to_le_bytes());
+    for (_, _, pix) in img.pixels() {
+        for component in pix.rgb().into_iter().chain(pix.alpha()) {
+            feed(&component.to_le_bytes());
+        }
+    }
+    hash
+}
+
+fn write_alpha(writer: &mut impl Write, alpha: Option<&[f32]>) -> io::Result<()> {
+    let Some(alpha) = alpha else {
+        return writer.write_all(&[0])
+    };
+    writer.write_all(&[1])?;
+    for value in alpha {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_alpha(reader: &mut impl Read, num_pixels: usize) -> io::Result<Option<Vec<f32>>> {
+    let mut has_alpha = [0u8; 1];
+    reader.read_exact(&mut has_alpha)?;
+    if has_alpha[0] == 0 {
+        return Ok(None)
+    }
+    let alpha = (0..num_pixels)
+        .map(|_| {
+            let mut buf = [0u8; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(f32::from_le_bytes(buf))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(Some(alpha))
+}
+
+fn read_color_space(reader: &mut impl Read) -> io::Result<ColorSpace> {
+    let mut tag = [0u8; 1];
+    reader.read_exact(&mut tag)?;
+    ColorSpace::from_tag(tag[0])
+        .ok_or_else(|| invalid_data(format!("Unknown color space {}", tag[0])))
+}
+
+/// Multiply two sizes read from a header, which may be corrupt.
+///
+/// Only the arithmetic needs checking: arrays are read one element at a time, so a size that is
+/// merely too large fails at the end of the stream instead of allocating memory up front.
+fn checked_mul(a: usize, b: usize) -> io::Result<usize> {
+    a.checked_mul(b).ok_or_else(|| invalid_data("Size too large for this platform"))
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_usize(writer: &mut impl Write, value: usize) -> io::Result<()> {
+    write_u64(writer, value as u64)
+}
+
+fn write_f64(writer: &mut impl Write, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_usize(reader: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(read_u64(reader)?)
+        .map_err(|_| invalid_data("Value too large for this platform"))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+
+#[test]
+fn test_image_weights_round_trip() {
+    use image::Rgb;
+
+    let img = crate::test_util::test_image();
+    let weights = ImageWeights::new(&img).unwrap();
+
+    let mut buf = vec![];
+    weights.save(&mut buf, &img).unwrap();
+    let loaded = ImageWeights::load_for_image(&mut &buf[..], &img).unwrap();
+
+    assert_eq!(loaded.width(), weights.width());
+    assert_eq!(loaded.height(), weights.height());
+    assert_eq!(loaded.weights, weights.weights);
+    assert_eq!(loaded.ch_rgb_vertices, weights.ch_rgb_vertices);
+    assert_eq!(loaded.color_space(), weights.color_space());
+    assert_eq!(loaded.spatial_weight(), weights.spatial_weight());
+
+    let mut other = img.clone();
+    other.put_pixel(0, 0, Rgb([1, 2, 3]));
+    assert!(!loaded.is_for_image(&other));
+    assert!(ImageWeights::load_for_image(&mut &buf[..], &other).is_err());
+    // The weights can only be saved along with an image of the same size.
+    let small = image::ImageBuffer::from_pixel(2, 2, Rgb([0u8, 0, 0]));
+    assert!(weights.save(&mut vec![], &small).is_err());
+
+    // Files from other versions of the format are rejected.
+    buf[4..8].copy_from_slice(&2u32.to_le_bytes());
+    assert!(ImageWeights::load(&mut &buf[..]).is_err());
+}
+
+#[test]
+fn test_decomposed_image_round_trip() {
+    use image::Rgb;
+
+    let img = crate::test_util::test_image();
+    let weights = ImageWeights::new(&img).unwrap();
+    let palette: [Rgb<u8>; 4] = [
+        Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255]),
+    ];
+    let decomposed = DecomposedImage::new(&weights, &palette).unwrap();
+
+    let mut buf = vec![];
+    decomposed.save(&mut buf, LayerPrecision::F64).unwrap();
+    let loaded = DecomposedImage::load(&mut &buf[..]).unwrap();
+    assert_eq!(loaded.palette::<Rgb<u8>>(), decomposed.palette::<Rgb<u8>>());
+    assert_eq!(loaded.star_vertex(), decomposed.star_vertex());
+    assert_eq!(loaded.matrix, decomposed.matrix);
+
+    let mut buf = vec![];
+    decomposed.save(&mut buf, LayerPrecision::F32).unwrap();
+    let loaded = DecomposedImage::load(&mut &buf[..]).unwrap();
+    assert_eq!(loaded.width(), decomposed.width());
+    assert_eq!(loaded.height(), decomposed.height());
+    assert!((&loaded.matrix - &decomposed.matrix).amax() < 1e-6);
+
+    // A corrupt header claiming a huge image is an error, not an overflow or a huge allocation.
+    for (width, height, num_channels) in [(u32::MAX, u32::MAX, u32::MAX), (100_000, 100_000, 4)] {
+        buf[8..12].copy_from_slice(&width.to_le_bytes());
+        buf[12..16].copy_from_slice(&height.to_le_bytes());
+        buf[16..20].copy_from_slice(&num_channels.to_le_bytes());
+        let Err(err) = DecomposedImage::load(&mut &buf[..]) else {
+            panic!("loaded a {width}x{height} image from {} bytes", buf.len())
+        };
+        assert!(matches!(err.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -0,0 +1,110 @@
+use image::{Pixel, Rgb, Rgba};
+
+/// Pixel types that can be used as the source image for a decomposition.
+///
+/// The decomposition only ever looks at the color of a pixel. If the pixel type has an alpha
+/// channel, it is carried alongside the decomposition untouched and can be recovered with
+/// `DecomposedImage::reconstruct_rgba`.
+///
+/// Floating point pixels are not clamped, so HDR images with components above 1.0 can be
+/// decomposed as well.
+pub trait SourcePixel: Pixel {
+    /// Whether the pixel type has an alpha channel, in which case `alpha` always returns `Some`.
+    const HAS_ALPHA: bool;
+
+    /// The red, green, and blue components of the pixel scaled to the range 0-1.
+    fn rgb(&self) -> [f64; 3];
+
+    /// The alpha component of the pixel scaled to the range 0-1, or `None` if the pixel type
+    /// doesn't have an alpha channel.
+    fn alpha(&self) -> Option<f64>;
+}
+
+/// Color types that can be used as a decomposition or reconstruction palette.
+pub trait PaletteColor: Copy {
+    /// The red, green, and blue components of the color scaled to the range 0-1.
+    fn to_rgb(&self) -> [f64; 3];
+
+    /// Build a color from components in the range 0-1.
+    ///
+    /// Integer color types clamp values outside of that range, floating point types keep them.
+    fn from_rgb(rgb: [f64; 3]) -> Self;
+}
+
+macro_rules! impl_integer_pixel {
+    ($t:ty) => {
+        impl SourcePixel for Rgb<$t> {
+            const HAS_ALPHA: bool = false;
+
+            fn rgb(&self) -> [f64; 3] {
+                let max = <$t>::MAX as f64;
+                [self[0] as f64 / max, self[1] as f64 / max, self[2] as f64 / max]
+            }
+
+            fn alpha(&self) -> Option<f64> {
+                None
+            }
+        }
+
+        impl SourcePixel for Rgba<$t> {
+            const HAS_ALPHA: bool = true;
+
+            fn rgb(&self) -> [f64; 3] {
+                let max = <$t>::MAX as f64;
+                [self[0] as f64 / max, self[1] as f64 / max, self[2] as f64 / max]
+            }
+
+            fn alpha(&self) -> Option<f64> {
+                Some(self[3] as f64 / <$t>::MAX as f64)
+            }
+        }
+
+        impl PaletteColor for Rgb<$t> {
+            fn to_rgb(&self) -> [f64; 3] {
+                SourcePixel::rgb(self)
+            }
+
+            fn from_rgb(rgb: [f64; 3]) -> Self {
+                let max = <$t>::MAX as f64;
+                Rgb(rgb.map(|c| (c.clamp(0.0, 1.0) * max).round() as $t))
+            }
+        }
+    };
+}
+
+impl_integer_pixel!(u8);
+impl_integer_pixel!(u16);
+
+impl SourcePixel for Rgb<f32> {
+    const HAS_ALPHA: bool = false;
+
+    fn rgb(&self) -> [f64; 3] {
+        [self[0] as f64, self[1] as f64, self[2] as f64]
+    }
+
+    fn alpha(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl SourcePixel for Rgba<f32> {
+    const HAS_ALPHA: bool = true;
+
+    fn rgb(&self) -> [f64; 3] {
+        [self[0] as f64, self[1] as f64, self[2] as f64]
+    }
+
+    fn alpha(&self) -> Option<f64> {
+        Some(self[3] as f64)
+    }
+}
+
+impl PaletteColor for Rgb<f32> {
+    fn to_rgb(&self) -> [f64; 3] {
+        SourcePixel::rgb(self)
+    }
+
+    fn from_rgb(rgb: [f64; 3]) -> Self {
+        Rgb(rgb.map(|c| c as f32))
+    }
+}
@@ -0,0 +1,17 @@
+use image::{ImageBuffer, Rgb, RgbImage};
+
+/// An 8x8 image whose colors spread out across the RGB cube. None of the channels are linear in
+/// the position, so its RGBXY hull is full dimensional.
+pub(crate) fn test_image() -> RgbImage {
+    ImageBuffer::from_fn(8, 8, |x, y| {
+        Rgb([(x * 29 + y * y) as u8, (y * 25 + x * x) as u8, ((x * y * 7 + 13) % 256) as u8])
+    })
+}
+
+/// The corners of the RGB cube, a palette that contains every color.
+pub(crate) fn cube_palette() -> [Rgb<u8>; 8] {
+    [
+        Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 255, 0]), Rgb([0, 0, 255]),
+        Rgb([255, 255, 0]), Rgb([255, 0, 255]), Rgb([0, 255, 255]), Rgb([255, 255, 255]),
+    ]
+}
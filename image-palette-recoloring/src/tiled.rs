@@ -0,0 +1,396 @@
+use std::borrow::Borrow;
+use std::io;
+
+use image::GenericImageView;
+
+use crate::{
+    DecomposedImage, DecompositionOptions, ImageWeights, ImageWeightsOptions, MaybeSync,
+    PaletteColor, RecolorError, SourcePixel,
+};
+
+/// Options that control how a `TiledDecomposition` splits up the image.
+#[derive(Clone, Debug)]
+pub struct TileOptions {
+    /// The width and height of each tile, not counting the overlap. Tiles on the right and bottom
+    /// edges of the image may be smaller.
+    pub tile_size: u32,
+    /// How many pixels each tile extends into its neighbors. Across the overlap, the result of one
+    /// tile is faded into the next so the seams between tiles don't show. Must be at most half of
+    /// `tile_size`.
+    pub overlap: u32,
+    /// The options used to compute the weights of each tile.
+    pub weights: ImageWeightsOptions,
+    /// The options used to decompose each tile.
+    pub decomposition: DecompositionOptions,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            tile_size: 512,
+            overlap: 16,
+            weights: ImageWeightsOptions::default(),
+            decomposition: DecompositionOptions::default(),
+        }
+    }
+}
+
+/// A decomposition of an image that is too large to decompose in one go.
+///
+/// The image is split into overlapping tiles and each tile gets its own `ImageWeights`, built from
+/// the RGBXY hull of just that tile, and its own `DecomposedImage`. `decompose_tiles` computes
+/// every tile once and hands it to the caller to keep, in memory or on disk with
+/// `DecomposedImage::save`. `reconstruct_rows` then recolors the image from the kept tiles as often
+/// as needed, streaming the result out row by row. It only asks for one row of tiles at a time, so
+/// the memory it needs is proportional to the width of the image times the tile size, no matter
+/// how tall the image is. This suits streaming encoders, such as the one of the `png` crate.
+///
+/// The positions of the pixels of every tile are normalized by the size of the whole image rather
+/// than the size of the tile, so `ImageWeightsOptions::spatial_weight` means the same thing as it
+/// does for an untiled image. The hull of each tile only covers that tile's pixels though, so the
+/// weights are still not the same as those of the untiled image.
+///
+/// The image is borrowed for as long as the `TiledDecomposition` lives, but its pixels are only
+/// read one tile at a time by `decompose_tile`, and `reconstruct_rows` only needs its size. An
+/// `ImageBuffer` holds the whole image in memory, so for an image that doesn't fit, pass a
+/// `GenericImageView` that reads its pixels on demand instead, for example from a memory-mapped
+/// file or a tiled image format.
+pub struct TiledDecomposition<'a, I, C> {
+    img: &'a I,
+    palette: Vec<C>,
+    options: TileOptions,
+}
+
+impl<'a, I, C> TiledDecomposition<'a, I, C>
+    where I: GenericImageView + MaybeSync,
+          I::Pixel: SourcePixel,
+          C: PaletteColor,
+{
+    /// Prepare to decompose `img` into the colors of `palette`, one tile at a time.
+    ///
+    /// Returns an error if the tile options are invalid or the palette is too small. Errors
+    /// specific to the colors of a tile, such as redundant palette colors, are only returned once
+    /// that tile is decomposed.
+    pub fn new(img: &'a I, palette: &[C], options: &TileOptions) -> Result<Self, RecolorError> {
+        if options.tile_size == 0 || options.overlap > options.tile_size / 2 {
+            return Err(RecolorError::InvalidTileSize {
+                tile_size: options.tile_size,
+                overlap: options.overlap,
+            })
+        }
+        if palette.len() < 2 {
+            return Err(RecolorError::PaletteTooSmall { min: 2, actual: palette.len() })
+        }
+        Ok(TiledDecomposition { img, palette: palette.to_vec(), options: options.clone() })
+    }
+
+    /// The width of the image.
+    pub fn width(&self) -> u32 {
+        self.img.width()
+    }
+
+    /// The height of the image.
+    pub fn height(&self) -> u32 {
+        self.img.height()
+    }
+
+    /// The number of channels of the decomposition, which is the number of colors in the palette.
+    pub fn num_channels(&self) -> usize {
+        self.palette.len()
+    }
+
+    /// The number of tiles across and down the image.
+    pub fn tile_count(&self) -> (u32, u32) {
+        let tile_size = self.options.tile_size;
+        (
+            self.img.width().div_ceil(tile_size),
+            self.img.height().div_ceil(tile_size),
+        )
+    }
+
+    /// The x, y, width and height of the part of the image covered by a tile, including the
+    /// overlap with its neighbors.
+    pub fn tile_bounds(&self, tile_x: u32, tile_y: u32) -> (u32, u32, u32, u32) {
+        let (x, x_end) = self.tile_span(tile_x, self.img.width());
+        let (y, y_end) = self.tile_span(tile_y, self.img.height());
+        (x, y, x_end - x, y_end - y)
+    }
+
+    /// Returns `true` if the image has an alpha channel, in which case `reconstruct_rows` writes
+    /// RGBA rows instead of RGB rows. This is decided by the pixel type of the image.
+    pub fn has_alpha(&self) -> bool {
+        I::Pixel::HAS_ALPHA
+    }
+
+    /// Decompose one tile. The result covers `tile_bounds(tile_x, tile_y)`.
+    ///
+    /// Unlike `ImageWeights::new`, a tile of a single color isn't an error. Large images often
+    /// have areas of solid color, and every pixel of such a tile is simply given that color.
+    ///
+    /// The tile's weights are computed with the spatial weight scaled by the size of the tile
+    /// relative to the image, which normalizes the pixel positions by the size of the image.
+    pub fn decompose_tile(&self, tile_x: u32, tile_y: u32)
+        -> Result<DecomposedImage, RecolorError>
+    {
+        let (x, y, width, height) = self.tile_bounds(tile_x, tile_y);
+        let tile = self.img.view(x, y, width, height);
+        let image_size = std::cmp::max(self.img.width(), self.img.height());
+        let options = ImageWeightsOptions {
+            spatial_weight: self.options.weights.spatial_weight
+                * std::cmp::max(width, height) as f64 / image_size as f64,
+            ..self.options.weights.clone()
+        };
+        let weights = match ImageWeights::with_options(&*tile, &options) {
+            Err(RecolorError::DegenerateHull(_)) => solid_color_weights(&*tile, &options),
+            result => result?,
+        };
+        DecomposedImage::with_options(&weights, &self.palette, &self.options.decomposition)
+    }
+
+    /// Decompose every tile and pass it to `store` along with its position, one row of tiles at a
+    /// time from top to bottom.
+    ///
+    /// Decomposing is the expensive part, so this only needs to be done once. Keep the tiles,
+    /// either in memory or on disk, and hand them back to `reconstruct_rows` for every recoloring.
+    /// Any error returned by `store` stops the decomposition and is returned as
+    /// `RecolorError::Io`.
+    pub fn decompose_tiles(
+        &self,
+        mut store: impl FnMut(u32, u32, DecomposedImage) -> io::Result<()>,
+    ) -> Result<(), RecolorError> {
+        let (tiles_x, tiles_y) = self.tile_count();
+        for tile_y in 0..tiles_y {
+            for tile_x in 0..tiles_x {
+                store(tile_x, tile_y, self.decompose_tile(tile_x, tile_y)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recolor the image from the tiles of `decompose_tiles` and pass each row of the result to
+    /// `write_row`, from top to bottom.
+    ///
+    /// `load_tile` is asked for the tiles one row of tiles at a time, and each tile is only asked
+    /// for once. Each row is given as packed 8-bit values, 4 bytes per pixel (RGBA) if the image
+    /// has an alpha channel and 3 bytes per pixel (RGB) otherwise. Any error returned by
+    /// `load_tile` or `write_row` stops the reconstruction and is returned as `RecolorError::Io`.
+    ///
+    /// Returns an error if the provided palette is not the same size as the palette used to build
+    /// the decomposition, or if a tile doesn't match its position.
+    pub fn reconstruct_rows<T: Borrow<DecomposedImage>>(
+        &self,
+        palette: &[C],
+        mut load_tile: impl FnMut(u32, u32) -> io::Result<T>,
+        mut write_row: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> Result<(), RecolorError> {
+        if palette.len() != self.num_channels() {
+            return Err(RecolorError::PaletteSizeMismatch {
+                expected: self.num_channels(),
+                actual: palette.len(),
+            })
+        }
+        let image_width = self.img.width() as usize;
+        let (tiles_x, tiles_y) = self.tile_count();
+        let num_components = if self.has_alpha() { 4 } else { 3 };
+
+        // The blended colors and alpha of the rows from `first_row` down that haven't been
+        // written yet. Rows in the overlap below a row of tiles have to wait for the next row of
+        // tiles.
+        let mut pending: Vec<[f32; 4]> = vec![];
+        let mut first_row = 0;
+        let mut bytes = vec![0u8; image_width * num_components];
+        for tile_y in 0..tiles_y {
+            let (_, y_end) = self.tile_span(tile_y, self.img.height());
+            pending.resize((y_end - first_row) as usize * image_width, [0.0; 4]);
+
+            for tile_x in 0..tiles_x {
+                let (x, y, width, height) = self.tile_bounds(tile_x, tile_y);
+                let tile = load_tile(tile_x, tile_y)?;
+                let tile = tile.borrow();
+                if tile.width() != width || tile.height() != height {
+                    return Err(RecolorError::TileMismatch { tile_x, tile_y })
+                }
+                let colors = tile.reconstruct_matrix(palette)?;
+                for (i, row) in colors.row_iter().enumerate() {
+                    let pixel_x = x + (i % width as usize) as u32;
+                    let pixel_y = y + (i / width as usize) as u32;
+                    let weight = self.blend_weight(pixel_x, tile_x, tiles_x, self.img.width())
+                        * self.blend_weight(pixel_y, tile_y, tiles_y, self.img.height());
+                    if weight == 0.0 {
+                        continue
+                    }
+                    let alpha = tile.alpha.as_ref().map_or(1.0, |alpha| alpha[i] as f64);
+                    let index = (pixel_y - first_row) as usize * image_width + pixel_x as usize;
+                    for (c, value) in [row[0], row[1], row[2], alpha].into_iter().enumerate() {
+                        pending[index][c] += (weight * value) as f32;
+                    }
+                }
+            }
+
+            // The rows above the next row of tiles are done.
+            let done = if tile_y + 1 < tiles_y {
+                self.tile_span(tile_y + 1, self.img.height()).0
+            } else {
+                self.img.height()
+            };
+            let done_len = (done - first_row) as usize * image_width;
+            for row in pending[..done_len].chunks(image_width) {
+                for (color, out) in row.iter().zip(bytes.chunks_mut(num_components)) {
+                    for (out, value) in out.iter_mut().zip(color) {
+                        *out = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+                write_row(&bytes)?;
+            }
+            pending.drain(..done_len);
+            first_row = done;
+        }
+        Ok(())
+    }
+
+    /// The range of pixels covered by a tile along one axis of the image, including the overlap.
+    fn tile_span(&self, tile: u32, len: u32) -> (u32, u32) {
+        let TileOptions { tile_size, overlap, .. } = self.options;
+        let start = tile * tile_size;
+        (start.saturating_sub(overlap), std::cmp::min(start + tile_size + overlap, len))
+    }
+
+    /// How much a tile contributes to the pixels at position `i` along one axis of the image.
+    ///
+    /// The contribution ramps linearly from one tile to the next across the overlap, so the
+    /// contributions of all the tiles covering a pixel add up to one.
+    fn blend_weight(&self, i: u32, tile: u32, tile_count: u32, len: u32) -> f64 {
+        let TileOptions { tile_size, overlap, .. } = self.options;
+        let start = tile * tile_size;
+        let end = std::cmp::min(start + tile_size, len);
+        if overlap == 0 {
+            return if (start..end).contains(&i) { 1.0 } else { 0.0 }
+        }
+        // The fraction of the way across the overlap around a seam that `i` is.
+        let ramp = |seam: u32| {
+            let across = (i as f64 - seam as f64 + overlap as f64 + 0.5) / (2 * overlap) as f64;
+            across.clamp(0.0, 1.0)
+        };
+        let mut weight = 1.0;
+        if tile > 0 {
+            weight *= ramp(start);
+        }
+        if tile + 1 < tile_count {
+            weight *= 1.0 - ramp(end);
+        }
+        weight
+    }
+}
+
+/// Weights for a tile that has at most one color, which `ImageWeights::with_options` rejects.
+fn solid_color_weights<P: SourcePixel>(
+    tile: &impl GenericImageView<Pixel = P>,
+    options: &ImageWeightsOptions,
+) -> ImageWeights {
+    let is_excluded = |pix: &P| options.exclude_transparent && pix.alpha() == Some(0.0);
+    let (weights, ch_rgb_vertices) =
+        crate::unique_color_weights(tile, options.color_space, is_excluded);
+    ImageWeights {
+        weights,
+        ch_rgb_vertices,
+        color_space: options.color_space,
+        spatial_weight: options.spatial_weight,
+        alpha: crate::image_alpha(tile),
+        width: tile.width(),
+        height: tile.height(),
+        source_hash: None,
+    }
+}
+
+
+#[test]
+fn test_tiled_reconstruction() {
+    use image::{ImageBuffer, Rgb};
+
+    // The left part of the image is a solid color, so the tiles on the left have a single color.
+    let img = ImageBuffer::from_fn(40, 30, |x, y| if x < 16 {
+        Rgb([200u8, 40, 90])
+    } else {
+        Rgb([(x * 6) as u8, (y * 8) as u8, ((x * y * 7 + 13) % 256) as u8])
+    });
+    let palette = crate::test_util::cube_palette();
+    let options = TileOptions { tile_size: 12, overlap: 3, ..TileOptions::default() };
+    let tiled = TiledDecomposition::new(&img, &palette, &options).unwrap();
+    assert_eq!(tiled.tile_count(), (4, 3));
+    assert_eq!(tiled.tile_bounds(1, 2), (9, 21, 18, 9));
+
+    let mut tiles = vec![];
+    tiled.decompose_tiles(|_, _, tile| {
+        tiles.push(tile);
+        Ok(())
+    }).unwrap();
+    assert_eq!(tiles.len(), 12);
+    let load_tile = |tile_x, tile_y| Ok(&tiles[(tile_y * 4 + tile_x) as usize]);
+
+    // Every tile reproduces its pixels, so the blended result reproduces the image.
+    assert!(!tiled.has_alpha());
+    let mut rows = vec![];
+    tiled.reconstruct_rows(&palette, load_tile, |row| {
+        rows.push(row.to_vec());
+        Ok(())
+    }).unwrap();
+    assert_eq!(rows.len(), 30);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, pix) in row.chunks(3).enumerate() {
+            let original = img.get_pixel(x as u32, y as u32);
+            for c in 0..3 {
+                assert!((pix[c] as i32 - original[c] as i32).abs() <= 1, "{x}, {y}");
+            }
+        }
+    }
+
+    // The blend weights of the tiles covering a pixel add up to one.
+    for x in 0..40 {
+        let total: f64 = (0..4).map(|tile| tiled.blend_weight(x, tile, 4, 40)).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    // A tile from the wrong position is rejected.
+    let result = tiled.reconstruct_rows(&palette, |_, _| Ok(&tiles[0]), |_| Ok(()));
+    assert!(matches!(result, Err(RecolorError::TileMismatch { tile_x: 1, tile_y: 0 })));
+
+    let options = TileOptions { tile_size: 12, overlap: 7, ..TileOptions::default() };
+    assert!(matches!(
+        TiledDecomposition::new(&img, &palette, &options),
+        Err(RecolorError::InvalidTileSize { .. })
+    ));
+}
+
+#[test]
+fn test_tiled_reconstruction_alpha() {
+    use image::{ImageBuffer, Rgba};
+
+    let img = ImageBuffer::from_fn(20, 20, |x, y| {
+        Rgba([(x * 12) as u8, (y * 12) as u8, ((x * y * 7 + 13) % 256) as u8, (x * 10) as u8])
+    });
+    let palette = crate::test_util::cube_palette();
+    let options = TileOptions { tile_size: 10, overlap: 2, ..TileOptions::default() };
+    let tiled = TiledDecomposition::new(&img, &palette, &options).unwrap();
+    assert!(tiled.has_alpha());
+
+    // The tiles are recomputed on demand here, which works just as well as keeping them.
+    let mut rows = vec![];
+    tiled.reconstruct_rows(
+        &palette,
+        |tile_x, tile_y| tiled.decompose_tile(tile_x, tile_y).map_err(io::Error::other),
+        |row| {
+            rows.push(row.to_vec());
+            Ok(())
+        },
+    ).unwrap();
+    for (y, row) in rows.iter().enumerate() {
+        assert_eq!(row.len(), 20 * 4);
+        for (x, pix) in row.chunks(4).enumerate() {
+            let original = img.get_pixel(x as u32, y as u32);
+            assert_eq!(pix[3], original[3]);
+            for c in 0..3 {
+                assert!((pix[c] as i32 - original[c] as i32).abs() <= 1, "{x}, {y}");
+            }
+        }
+    }
+}
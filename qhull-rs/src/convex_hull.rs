@@ -6,7 +6,7 @@ use nalgebra::{OVector, ToTypenum};
 use nalgebra::base::allocator::Allocator;
 use nalgebra::{Const, DefaultAllocator, DimName};
 
-use crate::RawHull;
+use crate::{Builder, QhullError, RawHull};
 
 /// N dimensional convex hull
 ///
@@ -97,6 +97,19 @@ impl<N> ConvexHull<N>
         }
     }
 
+    /// Compute the convex hull of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_vectors(slice: &[OVector<f64, N>]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawHull::try_from_vectors(slice)?))
+    }
+
+    /// Create a builder for computing a convex hull with custom qhull options.
+    ///
+    /// `Qt` is always included unless the input is joggled, since every facet needs to be a
+    /// simplex.
+    pub fn builder() -> Builder<Self> {
+        Builder::new("", crate::options::CONVEX_HULL_OPTIONS)
+    }
+
     // TODO: Create full iterator types for this.
     /// Iterator over all vertices of the convex hull.
     pub fn vertices<'a>(&'a self) -> impl ExactSizeIterator<Item = Vertex<'a, N>> + 'a {
@@ -117,6 +130,37 @@ impl<const N: usize> ConvexHull<Const<N>>
     pub fn from_arrays(slice: &[[f64; N]]) -> Self {
         Self::from_qhull_raw(RawHull::from_arrays(slice))
     }
+
+    /// Like `from_arrays`, but returns qhull's error instead of panicking.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawHull::try_from_arrays(slice)?))
+    }
+}
+
+impl<const N: usize> Builder<ConvexHull<Const<N>>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          DefaultAllocator: Allocator<usize, Const<N>>,
+          Const<N>: ToTypenum,
+{
+    /// Compute the convex hull of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<ConvexHull<Const<N>>, QhullError> {
+        let raw = self.simplicial::<RawHull<Const<N>>>().build(slice)?;
+        Ok(ConvexHull::from_qhull_raw(raw))
+    }
+}
+
+impl<N> Builder<ConvexHull<N>>
+    where DefaultAllocator: Allocator<f64, N>,
+          DefaultAllocator: Allocator<usize, N>,
+          N: DimName,
+{
+    /// Compute the convex hull of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, N>])
+        -> Result<ConvexHull<N>, QhullError>
+    {
+        let raw = self.simplicial::<RawHull<N>>().build_from_vectors(slice)?;
+        Ok(ConvexHull::from_qhull_raw(raw))
+    }
 }
 
 impl<N> FromIterator<OVector<f64, N>> for ConvexHull<N>
@@ -177,7 +221,7 @@ impl<'a, N> PartialEq for Vertex<'a, N>
           N: DimName,
 {
     fn eq(&self, other: &Self) -> bool {
-        (self.hull as *const _) == (other.hull as *const _) && self.idx == other.idx
+        std::ptr::eq(self.hull, other.hull) && self.idx == other.idx
     }
 }
 
@@ -276,3 +320,43 @@ fn test_construction() {
         [[2.0, 1.0], [2.0, 2.0]],
     ][..]);
 }
+
+#[test]
+fn test_degenerate_input_is_an_error() {
+    // Collinear points don't have a 2d convex hull
+    let points = [
+        [1.0, 1.0],
+        [2.0, 2.0],
+        [3.0, 3.0],
+        [4.0, 4.0],
+    ];
+    let err = ConvexHull::try_from_arrays(&points[..]).unwrap_err();
+    assert_ne!(err.code(), 0);
+    assert!(!err.message().is_empty());
+
+    // qhull should still be usable afterwards
+    let points = [
+        [1.0, 1.0],
+        [2.0, 1.0],
+        [1.0, 2.0],
+    ];
+    assert!(ConvexHull::try_from_arrays(&points[..]).is_ok());
+}
+
+#[test]
+fn test_builder_joggle() {
+    // Nearly-flat input: the last point is barely above the plane of the others
+    let points = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.5, 0.5, 1e-12],
+    ];
+    let cv = ConvexHull::builder()
+        .without(crate::Qhull::Qx)
+        .joggle(0.0)
+        .build(&points[..])
+        .unwrap();
+    assert!(cv.facets().all(|f| f.vertices().len() == 3));
+}
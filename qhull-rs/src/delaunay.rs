@@ -5,13 +5,16 @@ use nalgebra::{OMatrix, OVector, ToTypenum};
 use nalgebra::base::allocator::Allocator;
 use nalgebra::{Const, DefaultAllocator, DimAdd, DimMin, DimName, DimSum, U1};
 
-use crate::RawDelaunay;
+use crate::{Builder, QhullError, RawDelaunay};
 
 pub type Plus1<N> = DimSum<N, U1>;
 
 
 const NON_SIMPLEX_IDX: usize = usize::MAX;
 
+// The normal and offset of a simplex's facet on the lifted paraboloid.
+type NormalAndOffset<N> = (OVector<f64, Plus1<N>>, f64);
+
 
 /// N dimensional Deluanay triangulation.
 ///
@@ -28,7 +31,7 @@ pub struct Delaunay<N>
     vertices: Box<[OVector<f64, N>]>,
     simplices: Box<[OVector<usize, Plus1<N>>]>,
     neighbors: Box<[OVector<usize, Plus1<N>>]>,
-    normals_and_offsets: Box<[(OVector<f64, Plus1<N>>, f64)]>,
+    normals_and_offsets: Box<[NormalAndOffset<N>]>,
     // TODO: There are other things we might want to store, but these will work
     //       for now
 
@@ -166,6 +169,49 @@ impl<const N: usize> Delaunay<Const<N>>
     pub fn from_arrays(slice: &[[f64; N]]) -> Self {
         Self::from_qhull_raw(RawDelaunay::from_arrays(slice))
     }
+
+    /// Like `from_arrays`, but returns qhull's error instead of panicking.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawDelaunay::try_from_arrays(slice)?))
+    }
+
+    /// Like `try_from_arrays`, but for a slice of vectors.
+    pub fn try_from_vectors(slice: &[OVector<f64, Const<N>>]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawDelaunay::try_from_vectors(slice)?))
+    }
+
+    /// Create a builder for computing a Delaunay triangulation with custom qhull options.
+    ///
+    /// The `d` option is always included, as is `Qt` unless the input is joggled.
+    pub fn builder() -> Builder<Self> {
+        Builder::new("d", crate::options::DELAUNAY_OPTIONS)
+    }
+}
+
+impl<const N: usize> Builder<Delaunay<Const<N>>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          DefaultAllocator: Allocator<usize, Const<N>>,
+          DefaultAllocator: Allocator<usize, Plus1<Const<N>>>,
+          DefaultAllocator: Allocator<f64, Plus1<Const<N>>, Plus1<Const<N>>>,
+          Const<N>: ToTypenum + DimName + DimAdd<U1>,
+          Plus1<Const<N>>: DimName,
+          DefaultAllocator: Allocator<f64, nalgebra::DimMinimum<Plus1<Const<N>>, Plus1<Const<N>>>>,
+          DefaultAllocator: Allocator<(usize, usize), Plus1<Const<N>>>,
+          Plus1<Const<N>>: nalgebra::DimMin<Plus1<Const<N>>, Output = Plus1<Const<N>>>,
+{
+    /// Compute the Delaunay triangulation of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<Delaunay<Const<N>>, QhullError> {
+        let raw = self.simplicial::<RawDelaunay<Const<N>>>().build(slice)?;
+        Ok(Delaunay::from_qhull_raw(raw))
+    }
+
+    /// Compute the Delaunay triangulation of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, Const<N>>])
+        -> Result<Delaunay<Const<N>>, QhullError>
+    {
+        let raw = self.simplicial::<RawDelaunay<Const<N>>>().build_from_vectors(slice)?;
+        Ok(Delaunay::from_qhull_raw(raw))
+    }
 }
 
 /// A vertex of a Delaunay triangulation.
@@ -235,7 +281,7 @@ impl<'a, N> Simplex<'a, N>
             .map(move |&idx| if idx == NON_SIMPLEX_IDX {
                     None
                 } else {
-                    Some(Simplex { delaunay, idx: idx })
+                    Some(Simplex { delaunay, idx })
                 })
 
     }
@@ -245,7 +291,7 @@ impl<'a, N> Simplex<'a, N>
         let (normal, offset) = self.delaunay.normals_and_offsets.get(self.idx).unwrap();
         let mut sum = *offset;
         for (a, b) in point.as_slice().iter().zip(normal.as_slice()) {
-            sum += a + b;
+            sum += a * b;
         }
         sum
     }
@@ -399,7 +445,7 @@ impl<'a, N> SimplexSearcher<'a, N>
         }
 
 
-        let start_simplex_idx = self.start_hint.get().unwrap_or(1);
+        let start_simplex_idx = self.start_hint.get().unwrap_or(0);
         let mut simplex = Simplex { delaunay: self.delaunay, idx: start_simplex_idx };
 
         let lifted_point = self.delaunay.lift_point(point);
@@ -462,9 +508,9 @@ impl<'a, N> SimplexSearcher<'a, N>
                         // This neighbor is degenerate too, skip it
                         continue
                     }
+                    transform.solve(point.clone(), bcoords);
                     let inside = self.delaunay.neighbors[neighbor].iter()
                         .zip(bcoords.iter())
-                        .filter(|(i, _)| **i != NON_SIMPLEX_IDX)
                         .all(|(inner_neighbor, coord)| {
                             if inner_neighbor == &i {
                                 *coord >= -eps_broad && *coord <= 1.0 + self.eps
@@ -612,9 +658,20 @@ impl<N> BarycentricTransformation<N>
                 .map(move |vert| vert[i] - rn)
             );
         let t = OMatrix::<f64, N, N>::from_row_iterator(iter);
-        let decomp = t.lu();
+        let decomp = t.clone().lu();
         let mut t_inv = OMatrix::<f64, N, N>::from_element(f64::NAN);
-        let _ = decomp.try_inverse_to(&mut t_inv);
+        // Triangulated output can contain simplices that are flat, or flat up to rounding error.
+        // The inverse of the latter exists but is garbage, and so are any coordinates computed
+        // with it, so treat them as degenerate like SciPy does. The reciprocal condition number
+        // is estimated with the 1-norm.
+        let norm1 = |m: &OMatrix<f64, N, N>| m.column_iter()
+            .map(|column| column.iter().map(|v| v.abs()).sum::<f64>())
+            .fold(0.0, f64::max);
+        let invertible = decomp.try_inverse_to(&mut t_inv);
+        if !invertible || !(1.0 / (norm1(&t) * norm1(&t_inv)) >= 100.0 * f64::EPSILON) {
+            // `try_inverse_to` leaves a partial result behind when it fails.
+            t_inv.fill(f64::NAN);
+        }
 
         BarycentricTransformation {
             t_inv,
@@ -693,3 +750,86 @@ fn test_construction() {
         [[1.0, 1.0], [2.0, 1.0], [2.0, 2.0]],
     ][..]);
 }
+
+#[test]
+fn test_plane_distance() {
+    let points = [[0.0, 0.0], [4.0, 0.0], [0.0, 4.0], [4.0, 4.5], [1.5, 1.0]];
+    let tri = Delaunay::from_arrays(&points[..]);
+    for simplex in tri.simplices() {
+        for vertex in tri.vertices() {
+            // The lifted vertices of the simplex lie on its plane, the rest lie above it.
+            let dist = simplex.plane_distance(&tri.lift_point(vertex.point()));
+            if simplex.vertices().any(|v| v.index() == vertex.index()) {
+                assert!(dist.abs() < 1e-9, "{}", dist);
+            } else {
+                assert!(dist < 0.0, "{}", dist);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_find_simplex_near_facets() {
+    let tri = Delaunay::from_arrays(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]][..]);
+    let simplex = tri.simplex_searcher().find_simplex(&[0.25, 0.25].into()).unwrap();
+    assert_eq!(simplex.index(), 0);
+
+    // Points just inside of every edge of every simplex, which are easy to attribute to the
+    // neighbor on the other side of the edge.
+    let points = [[0.0, 0.0], [4.0, 0.0], [0.0, 4.0], [4.0, 4.5], [1.5, 1.0], [3.0, 1.5]];
+    let tri = Delaunay::from_arrays(&points[..]);
+    let searcher = tri.simplex_searcher();
+    for simplex in tri.simplices() {
+        let vertices = simplex.vertices().map(|v| *v.point()).collect::<Vec<_>>();
+        for i in 0..3 {
+            let midpoint = (vertices[(i + 1) % 3] + vertices[(i + 2) % 3]) / 2.0;
+            let point = midpoint + (vertices[i] - midpoint) * 1e-6;
+            let found = searcher.find_simplex(&point).unwrap();
+            assert_eq!(found.index(), simplex.index(), "{:?}", point);
+        }
+    }
+}
+
+#[test]
+fn test_find_simplex_near_flat_simplices() {
+    // The points of a grid are cospherical in groups of eight, so its triangulation contains flat
+    // simplices. Jittering the grid a little turns them into simplices that are flat up to
+    // rounding error.
+    for jitter in [0.0, 1e-13] {
+        let mut seed = 1u64;
+        let mut jitter = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * jitter
+        };
+        let mut points = vec![];
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    points.push([x as f64 + jitter(), y as f64 + jitter(), z as f64 + jitter()]);
+                }
+            }
+        }
+        let tri = Delaunay::from_arrays(&points[..]);
+
+        let mut searcher = tri.simplex_searcher();
+        assert!(searcher.transforms.iter().any(|t| t.is_degenerate()));
+        let mut coords = OVector::<f64, nalgebra::U4>::zeros();
+        for bruteforce in [false, true] {
+            searcher.set_bruteforce(bruteforce);
+            for i in 0..125 {
+                let point = OVector::<f64, nalgebra::U3>::new(
+                    (i % 5) as f64 * 0.45 + 0.1,
+                    (i / 5 % 5) as f64 * 0.45 + 0.1,
+                    (i / 25) as f64 * 0.45 + 0.1,
+                );
+                let simplex = searcher.find_simplex_mut(&point, &mut coords).unwrap();
+                assert!(coords.iter().all(|c| (-1e-9..=1.0 + 1e-9).contains(c)), "{:?}", coords);
+                let rebuilt = simplex.vertices()
+                    .zip(coords.iter())
+                    .map(|(v, c)| v.point() * *c)
+                    .sum::<OVector<f64, nalgebra::U3>>();
+                assert!((rebuilt - point).norm() < 1e-9, "{:?} {:?}", point, rebuilt);
+            }
+        }
+    }
+}
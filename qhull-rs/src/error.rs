@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fmt;
+
+/// An error reported by qhull while constructing a hull or triangulation.
+///
+/// This carries qhull's exit code along with any text qhull printed while it was running, which
+/// usually explains what went wrong (for example, that the input is flat or that a precision error
+/// occurred).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QhullError {
+    code: i32,
+    message: String,
+}
+
+impl QhullError {
+    pub(crate) fn new(code: i32, message: String) -> Self {
+        QhullError { code, message }
+    }
+
+    /// qhull's exit code. See `QhullErrorKind` for what the codes mean.
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// The kind of error, based on the exit code.
+    pub fn kind(&self) -> QhullErrorKind {
+        match self.code {
+            1 => QhullErrorKind::Input,
+            2 => QhullErrorKind::Singular,
+            3 => QhullErrorKind::Precision,
+            4 => QhullErrorKind::Memory,
+            5 => QhullErrorKind::Internal,
+            7 => QhullErrorKind::Topology,
+            8 => QhullErrorKind::Wide,
+            _ => QhullErrorKind::Other,
+        }
+    }
+
+    /// The text qhull printed while it was running.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for QhullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qhull exited with code {}", self.code)?;
+        let message = self.message.trim();
+        if !message.is_empty() {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for QhullError { }
+
+/// The kinds of errors qhull can report. These correspond to the `qh_ERR*` exit codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QhullErrorKind {
+    /// The input or options were invalid (`qh_ERRinput`).
+    Input,
+    /// The input was singular, eg. all of the points are on a hyperplane (`qh_ERRsingular`).
+    Singular,
+    /// qhull hit a precision error (`qh_ERRprec`). Joggling the input may help.
+    Precision,
+    /// qhull ran out of memory (`qh_ERRmem`).
+    Memory,
+    /// qhull detected an internal error (`qh_ERRqhull`).
+    Internal,
+    /// A topological error, eg. a facet with too many neighbors (`qh_ERRtopology`).
+    Topology,
+    /// A precision error caused by a wide merge (`qh_ERRwide`).
+    Wide,
+    /// Any other exit code.
+    Other,
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use nalgebra::OVector;
+use nalgebra::base::allocator::Allocator;
+use nalgebra::{Const, DefaultAllocator, DimName};
+
+use crate::{Builder, QhullError, RawHalfspaceIntersection};
+
+/// N dimensional halfspace intersection.
+///
+/// Each halfspace is a normal and an offset, and contains the points `x` where
+/// `normal.dot(x) + offset <= 0`. This is the same convention used for the facets of a
+/// `ConvexHull`, so a hull's facets can be intersected with other halfspaces directly.
+///
+/// This type stores the data from qhull in a struct-of-arrays format modeled after
+/// `scipy.spatial.HalfspaceIntersection`.
+#[derive(Clone, Debug)]
+pub struct HalfspaceIntersection<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName,
+{
+    halfspaces: Box<[(OVector<f64, N>, f64)]>,
+    interior_point: OVector<f64, N>,
+    vertices: Box<[OVector<f64, N>]>,
+    vertex_halfspaces: Box<[Box<[usize]>]>,
+    facets: Box<[Box<[usize]>]>,
+    facet_halfspaces: Box<[usize]>,
+    is_bounded: bool,
+}
+
+impl<N> HalfspaceIntersection<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName,
+{
+    /// Constructs a `HalfspaceIntersection` from the raw qhull wrapper type.
+    ///
+    /// This is not the recommended way to construct an instance of this type. You probably want to
+    /// use the `from_arrays` method.
+    pub fn from_qhull_raw(intersection: RawHalfspaceIntersection<N>) -> Self {
+        let mut is_bounded = true;
+        let mut vertices = vec![];
+        let mut vertex_halfspaces = vec![];
+        let mut vertex_id_map = HashMap::<u64, usize>::new();
+        for facet in intersection.facets() {
+            let Some(point) = intersection.intersection_point(&facet) else {
+                is_bounded = false;
+                continue
+            };
+            vertex_id_map.insert(facet.id(), vertices.len());
+            vertices.push(point);
+            vertex_halfspaces.push(
+                facet.vertices()
+                    .filter_map(|v| intersection.halfspace_index(&v))
+                    .collect::<Box<[_]>>()
+            );
+        }
+
+        let mut facets = vec![];
+        let mut facet_halfspaces = vec![];
+        for vertex in intersection.vertices() {
+            let Some(halfspace) = intersection.halfspace_index(&vertex) else {
+                continue
+            };
+            facets.push(
+                intersection.ordered_neighbors(&vertex).into_iter()
+                    .filter_map(|f| vertex_id_map.get(&f.id()).copied())
+                    .collect::<Box<[_]>>()
+            );
+            facet_halfspaces.push(halfspace);
+        }
+
+        HalfspaceIntersection {
+            halfspaces: intersection.halfspaces().into(),
+            interior_point: intersection.interior_point().clone(),
+            vertices: vertices.into_boxed_slice(),
+            vertex_halfspaces: vertex_halfspaces.into_boxed_slice(),
+            facets: facets.into_boxed_slice(),
+            facet_halfspaces: facet_halfspaces.into_boxed_slice(),
+            is_bounded,
+        }
+    }
+
+    /// Compute the intersection of `halfspaces`, returning qhull's error on failure.
+    /// `interior_point` must be clearly inside all of them.
+    pub fn try_from_vectors(
+        halfspaces: &[(OVector<f64, N>, f64)],
+        interior_point: &OVector<f64, N>,
+    ) -> Result<Self, QhullError> {
+        let raw = RawHalfspaceIntersection::try_from_vectors(halfspaces, interior_point)?;
+        Ok(Self::from_qhull_raw(raw))
+    }
+
+    /// Create a builder for computing a halfspace intersection with custom qhull options.
+    pub fn builder() -> Builder<Self> {
+        RawHalfspaceIntersection::<N>::builder().cast()
+    }
+
+    /// The input halfspaces.
+    pub fn halfspaces(&self) -> &[(OVector<f64, N>, f64)] {
+        &self.halfspaces
+    }
+
+    /// The interior point the intersection was computed about.
+    pub fn interior_point(&self) -> &OVector<f64, N> {
+        &self.interior_point
+    }
+
+    /// The vertices of the intersection.
+    pub fn vertices(&self) -> &[OVector<f64, N>] {
+        &self.vertices
+    }
+
+    /// The indices of the halfspaces whose boundaries meet at each vertex.
+    pub fn vertex_halfspaces(&self) -> &[Box<[usize]>] {
+        &self.vertex_halfspaces
+    }
+
+    /// The indices of the vertices of each facet of the intersection.
+    ///
+    /// For 3D intersections, the vertices are in order around the facet.
+    pub fn facets(&self) -> &[Box<[usize]>] {
+        &self.facets
+    }
+
+    /// The index of the halfspace each facet lies on, in the same order as `facets`.
+    ///
+    /// Redundant halfspaces, which don't touch the intersection, don't have a facet.
+    pub fn facet_halfspaces(&self) -> &[usize] {
+        &self.facet_halfspaces
+    }
+
+    /// Returns false if the intersection is unbounded. The vertices at infinity are left out of
+    /// `vertices` and `facets`.
+    pub fn is_bounded(&self) -> bool {
+        self.is_bounded
+    }
+}
+
+impl<const N: usize> HalfspaceIntersection<Const<N>> {
+    /// Compute the intersection of `halfspaces`. `interior_point` must be clearly inside all of
+    /// them.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
+    pub fn from_arrays(halfspaces: &[([f64; N], f64)], interior_point: [f64; N]) -> Self {
+        Self::from_qhull_raw(RawHalfspaceIntersection::from_arrays(halfspaces, interior_point))
+    }
+
+    /// Like `from_arrays`, but returns qhull's error instead of panicking.
+    pub fn try_from_arrays(halfspaces: &[([f64; N], f64)], interior_point: [f64; N])
+        -> Result<Self, QhullError>
+    {
+        let raw = RawHalfspaceIntersection::try_from_arrays(halfspaces, interior_point)?;
+        Ok(Self::from_qhull_raw(raw))
+    }
+}
+
+impl<const N: usize> Builder<HalfspaceIntersection<Const<N>>> {
+    /// Compute the intersection of `halfspaces` using these options.
+    pub fn build(&self, halfspaces: &[([f64; N], f64)], interior_point: [f64; N])
+        -> Result<HalfspaceIntersection<Const<N>>, QhullError>
+    {
+        let raw = self.cast::<RawHalfspaceIntersection<Const<N>>>()
+            .build(halfspaces, interior_point)?;
+        Ok(HalfspaceIntersection::from_qhull_raw(raw))
+    }
+}
+
+impl<N> Builder<HalfspaceIntersection<N>>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName,
+{
+    /// Compute the intersection of `halfspaces` using these options.
+    pub fn build_from_vectors(
+        &self,
+        halfspaces: &[(OVector<f64, N>, f64)],
+        interior_point: &OVector<f64, N>,
+    ) -> Result<HalfspaceIntersection<N>, QhullError> {
+        let raw = self.cast::<RawHalfspaceIntersection<N>>()
+            .build_from_vectors(halfspaces, interior_point)?;
+        Ok(HalfspaceIntersection::from_qhull_raw(raw))
+    }
+}
+
+
+#[test]
+fn test_unit_cube() {
+    let halfspaces = [
+        ([-1.0, 0.0, 0.0], 0.0),
+        ([1.0, 0.0, 0.0], -1.0),
+        ([0.0, -1.0, 0.0], 0.0),
+        ([0.0, 1.0, 0.0], -1.0),
+        ([0.0, 0.0, -1.0], 0.0),
+        ([0.0, 0.0, 1.0], -1.0),
+        // Redundant
+        ([1.0, 0.0, 0.0], -2.0),
+    ];
+    let cube = HalfspaceIntersection::from_arrays(&halfspaces, [0.25, 0.5, 0.5]);
+    assert!(cube.is_bounded());
+
+    let mut vertices = cube.vertices().iter()
+        .map(|v| [v[0].round(), v[1].round(), v[2].round()])
+        .collect::<Vec<_>>();
+    vertices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(vertices, [
+        [0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [0.0, 1.0, 1.0],
+        [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0],
+    ]);
+    assert!(cube.vertex_halfspaces().iter().all(|h| h.len() == 3 && !h.contains(&6)));
+
+    let mut facet_halfspaces = cube.facet_halfspaces().to_vec();
+    facet_halfspaces.sort();
+    assert_eq!(facet_halfspaces, [0, 1, 2, 3, 4, 5]);
+    for (facet, &halfspace) in cube.facets().iter().zip(cube.facet_halfspaces()) {
+        assert_eq!(facet.len(), 4);
+        let (normal, offset) = &cube.halfspaces()[halfspace];
+        for &v in facet.iter() {
+            assert!((normal.dot(&cube.vertices()[v]) + offset).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn test_infeasible_interior_point() {
+    let halfspaces = [
+        ([-1.0, 0.0], 0.0),
+        ([1.0, 0.0], -1.0),
+        ([0.0, -1.0], 0.0),
+        ([0.0, 1.0], -1.0),
+    ];
+    let result = HalfspaceIntersection::try_from_arrays(&halfspaces, [2.0, 0.5]);
+    assert!(result.is_err());
+}
@@ -1,10 +1,27 @@
-/// Type-safe wrappers for the qhull library.
+//! Type-safe wrappers for the qhull library.
 
+// The negated comparisons are deliberate, they treat NaN as failing the comparison.
+#![allow(clippy::neg_cmp_op_on_partial_ord)]
+
+mod error;
+mod options;
 pub mod raw;
 pub mod convex_hull;
 pub mod delaunay;
+pub mod voronoi;
+pub mod halfspace;
+
+pub use crate::raw::{
+    ConvexHull as RawHull,
+    Delaunay as RawDelaunay,
+    HalfspaceIntersection as RawHalfspaceIntersection,
+    Voronoi as RawVoronoi,
+};
 
-pub use crate::raw::{ConvexHull as RawHull, Delaunay as RawDelaunay};
+pub use error::{QhullError, QhullErrorKind};
+pub use options::{Builder, Qhull};
 
 pub use convex_hull::ConvexHull;
 pub use delaunay::Delaunay;
+pub use voronoi::Voronoi;
+pub use halfspace::HalfspaceIntersection;
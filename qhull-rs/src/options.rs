@@ -0,0 +1,229 @@
+use std::fmt::Write;
+use std::marker::PhantomData;
+
+/// Common qhull options that can be passed to a `Builder`.
+///
+/// See the qhull documentation (`qh-optq.htm` and `qh-optp.htm`) for the details of each option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Qhull {
+    /// `Qbb`: Scale the last coordinate to [0, m] where m is the maximum absolute value of the
+    /// other coordinates. Improves precision for Delaunay triangulations.
+    Qbb,
+    /// `QbB`: Scale the input to the unit cube.
+    QbB,
+    /// `Qc`: Keep coplanar points with the nearest facet.
+    Qc,
+    /// `Qi`: Keep interior points with the nearest facet.
+    Qi,
+    /// `Qs`: Search all points for the initial simplex.
+    Qs,
+    /// `Qt`: Triangulate the output, so every facet is a simplex.
+    Qt,
+    /// `Qv`: Test vertex neighbors for convexity.
+    Qv,
+    /// `Qx`: Exact pre-merges. Skips coplanar and angle-coplanar facets.
+    Qx,
+    /// `Qz`: Add a point at infinity. Improves precision for Delaunay triangulations of
+    /// cospherical points.
+    Qz,
+    /// `Q12`: Allow wide facets and wide dupridges instead of reporting an error.
+    Q12,
+    /// `Pp`: Don't report precision problems.
+    Pp,
+}
+
+impl Qhull {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Qhull::Qbb => "Qbb",
+            Qhull::QbB => "QbB",
+            Qhull::Qc => "Qc",
+            Qhull::Qi => "Qi",
+            Qhull::Qs => "Qs",
+            Qhull::Qt => "Qt",
+            Qhull::Qv => "Qv",
+            Qhull::Qx => "Qx",
+            Qhull::Qz => "Qz",
+            Qhull::Q12 => "Q12",
+            Qhull::Pp => "Pp",
+        }
+    }
+}
+
+/// The options used by `ConvexHull::from_arrays` and friends.
+pub(crate) const CONVEX_HULL_OPTIONS: &[Qhull] = &[Qhull::Qc, Qhull::Qx, Qhull::Qt];
+
+/// The options used by `Delaunay::from_arrays` and friends.
+pub(crate) const DELAUNAY_OPTIONS: &[Qhull] = &[
+    Qhull::Qc, Qhull::Qx, Qhull::Qbb, Qhull::Q12, Qhull::Qt, Qhull::Qz,
+];
+
+/// The options used by `Voronoi::from_arrays` and friends. These match SciPy's defaults.
+pub(crate) const VORONOI_OPTIONS: &[Qhull] = &[Qhull::Qbb, Qhull::Qc, Qhull::Qz];
+
+/// The options used by `HalfspaceIntersection::from_arrays` and friends. These match SciPy's
+/// defaults.
+pub(crate) const HALFSPACE_OPTIONS: &[Qhull] = &[];
+
+/// Builder for configuring the options qhull is run with.
+///
+/// Create one with the `builder()` function of `ConvexHull`, `Delaunay`, `Voronoi` or
+/// `HalfspaceIntersection` (or the raw equivalents). The builder starts out with the same options
+/// the plain constructors use, so only the differences need to be specified.
+///
+/// The `ConvexHull` and `Delaunay` types require simplicial facets, so `Qt` is added
+/// automatically for those unless the input is joggled.
+///
+/// ```ignore
+/// let hull = ConvexHull::builder()
+///     .without(Qhull::Qx)
+///     .joggle(0.0)
+///     .build(&points)?;
+/// ```
+#[derive(Debug)]
+pub struct Builder<T> {
+    mode: &'static str,
+    options: Vec<Qhull>,
+    joggle: Option<f64>,
+    premerge_centrum: Option<f64>,
+    premerge_angle: Option<f64>,
+    pd: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Builder<T> {
+    fn clone(&self) -> Self {
+        Builder {
+            mode: self.mode,
+            options: self.options.clone(),
+            joggle: self.joggle,
+            premerge_centrum: self.premerge_centrum,
+            premerge_angle: self.premerge_angle,
+            pd: PhantomData,
+        }
+    }
+}
+
+impl<T> Builder<T> {
+    /// `mode` is the qhull option selecting what is computed, eg. `d` for a Delaunay
+    /// triangulation, or an empty string for a convex hull.
+    pub(crate) fn new(mode: &'static str, options: &[Qhull]) -> Self {
+        Builder {
+            mode,
+            options: options.to_vec(),
+            joggle: None,
+            premerge_centrum: None,
+            premerge_angle: None,
+            pd: PhantomData,
+        }
+    }
+
+    /// Enable an option.
+    pub fn option(mut self, option: Qhull) -> Self {
+        if !self.options.contains(&option) {
+            self.options.push(option);
+        }
+        self
+    }
+
+    /// Disable an option, including one that is enabled by default.
+    pub fn without(mut self, option: Qhull) -> Self {
+        self.options.retain(|o| *o != option);
+        self
+    }
+
+    /// Disable all options, including the defaults.
+    pub fn clear(mut self) -> Self {
+        self.options.clear();
+        self.joggle = None;
+        self.premerge_centrum = None;
+        self.premerge_angle = None;
+        self
+    }
+
+    /// `QJn`: Joggle each input coordinate by a random amount up to `amount`.
+    ///
+    /// Joggling avoids precision errors at the cost of a slightly perturbed result. The output is
+    /// always simplicial. Passing `0.0` lets qhull pick the amount.
+    pub fn joggle(mut self, amount: f64) -> Self {
+        self.joggle = Some(amount);
+        self
+    }
+
+    /// `C-n`: Pre-merge facets whose centrums are within `radius` of each other.
+    pub fn premerge_centrum(mut self, radius: f64) -> Self {
+        self.premerge_centrum = Some(radius);
+        self
+    }
+
+    /// `A-n`: Pre-merge facets whose normals have a cosine greater than `cosine`.
+    pub fn premerge_angle(mut self, cosine: f64) -> Self {
+        self.premerge_angle = Some(cosine);
+        self
+    }
+
+    /// Copy these options into a builder for another type.
+    pub(crate) fn cast<U>(&self) -> Builder<U> {
+        Builder {
+            mode: self.mode,
+            options: self.options.clone(),
+            joggle: self.joggle,
+            premerge_centrum: self.premerge_centrum,
+            premerge_angle: self.premerge_angle,
+            pd: PhantomData,
+        }
+    }
+
+    /// Copy these options into a builder for another type, adding `Qt` if needed to ensure that
+    /// every facet is a simplex.
+    pub(crate) fn simplicial<U>(&self) -> Builder<U> {
+        let builder = self.cast::<U>();
+        if builder.joggle.is_some() {
+            builder
+        } else {
+            builder.option(Qhull::Qt)
+        }
+    }
+
+    /// The qhull command string for these options, adding `Qt` if needed to ensure that every
+    /// facet is a simplex.
+    pub(crate) fn simplicial_command(&self) -> String {
+        self.simplicial::<T>().command()
+    }
+
+    /// The qhull command string for these options.
+    pub fn command(&self) -> String {
+        let mut cmd = String::from("qhull");
+        if !self.mode.is_empty() {
+            cmd.push(' ');
+            cmd.push_str(self.mode);
+        }
+        for option in &self.options {
+            cmd.push(' ');
+            cmd.push_str(option.as_str());
+        }
+        if let Some(amount) = self.joggle {
+            write!(cmd, " QJ{}", amount).unwrap();
+        }
+        if let Some(radius) = self.premerge_centrum {
+            write!(cmd, " C-{}", radius).unwrap();
+        }
+        if let Some(cosine) = self.premerge_angle {
+            write!(cmd, " A-{}", cosine).unwrap();
+        }
+        cmd
+    }
+}
+
+#[test]
+fn test_default_commands() {
+    use crate::raw::{ConvexHull, Delaunay};
+    use nalgebra::Const;
+
+    assert_eq!(ConvexHull::<Const<3>>::builder().command(), "qhull Qc Qx Qt");
+    assert_eq!(Delaunay::<Const<3>>::builder().command(), "qhull d Qc Qx Qbb Q12 Qt Qz");
+    assert_eq!(
+        ConvexHull::<Const<3>>::builder().without(Qhull::Qx).joggle(1e-6).command(),
+        "qhull Qc Qt QJ0.000001",
+    );
+}
@@ -1,6 +1,8 @@
 
 use qhull_sys::qhT;
 
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
 use std::fmt;
 use std::iter::{self, FromIterator};
 use std::marker::PhantomData;
@@ -10,6 +12,81 @@ use nalgebra::{OMatrix, OVector};
 use nalgebra::base::allocator::{Allocator, Reallocator};
 use nalgebra::{Const, DefaultAllocator, DimAdd, DimName, DimSum, U1};
 
+use crate::QhullError;
+use crate::options::{
+    Builder, CONVEX_HULL_OPTIONS, DELAUNAY_OPTIONS, HALFSPACE_OPTIONS, VORONOI_OPTIONS,
+};
+
+/// Copy `slice` into a buffer allocated with `malloc` so that qhull can take ownership of it.
+unsafe fn malloc_points<T>(slice: &[T]) -> *mut f64 {
+    let len = std::mem::size_of_val(slice);
+    let buf = libc::malloc(len);
+    libc::memcpy(buf, slice.as_ptr().cast(), len);
+    buf.cast()
+}
+
+/// Run qhull with the options in `cmd` over the `len` points of dimension `dim` in `buf`.
+///
+/// qhull takes ownership of `buf`, which must have been allocated with `malloc`. Errors are
+/// captured rather than exiting the process.
+unsafe fn run_qhull(cmd: &str, buf: *mut f64, len: usize, dim: usize)
+    -> Result<NonNull<qhT>, QhullError>
+{
+    run_qhull_feasible(cmd, buf, len, dim, ptr::null_mut())
+}
+
+/// Like `run_qhull`, but for halfspace intersection. `buf` holds the halfspaces and `feasible` the
+/// interior point, which has one fewer dimension than `dim`.
+unsafe fn run_qhull_feasible(cmd: &str, buf: *mut f64, len: usize, dim: usize, feasible: *mut f64)
+    -> Result<NonNull<qhT>, QhullError>
+{
+    let qh = NonNull::new(qhull_sys::rqh_malloc()).expect("failed to allocate qhT");
+    let stdin = qhull_sys::rqh_stdin();
+    let stdout = qhull_sys::rqh_stdout();
+    let stderr = qhull_sys::rqh_stderr();
+    qhull_sys::qh_init_A(qh.as_ptr(), stdin, stdout, stderr, 0, ptr::null_mut());
+
+    let mut cmd = CString::new(cmd).expect("qhull options can't contain nul bytes")
+        .into_bytes_with_nul();
+    let mut message = ptr::null_mut();
+    let code = qhull_sys::rqh_run_qhull(
+        qh.as_ptr(),
+        cmd.as_mut_ptr().cast(),
+        buf,
+        len as _,
+        dim as _,
+        feasible,
+        &mut message,
+    );
+    if code == 0 {
+        return Ok(qh)
+    }
+
+    let message = if message.is_null() {
+        String::new()
+    } else {
+        let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+        libc::free(message.cast());
+        text
+    };
+    free_qhull(qh);
+    Err(QhullError::new(code as i32, message))
+}
+
+unsafe fn free_qhull(qh: NonNull<qhT>) {
+    qhull_sys::qh_freeqhull(qh.as_ptr(), 0);
+
+    let mut tolong = 0;
+    let mut currlong = 0;
+    qhull_sys::qh_memfreeshort(
+        qh.as_ptr(),
+        ptr::addr_of_mut!(currlong),
+        ptr::addr_of_mut!(tolong)
+    );
+
+    libc::free(qh.as_ptr().cast());
+}
+
 
 /// Nth dimensional convex hull
 ///
@@ -23,48 +100,72 @@ pub struct ConvexHull<N: DimName> {
 }
 
 impl<const N: usize> ConvexHull<Const<N>> {
+    /// Compute the convex hull of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
     pub fn from_arrays(slice: &[[f64; N]]) -> Self {
+        Self::try_from_arrays(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the convex hull of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Self::builder().build(slice)
+    }
+}
+
+impl<const N: usize> Builder<ConvexHull<Const<N>>> {
+    /// Compute the convex hull of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<ConvexHull<Const<N>>, QhullError> {
+        unsafe {
+            ConvexHull::try_init_shared(malloc_points(slice), slice.len(), &self.command())
+        }
+    }
+}
+
+impl<N: DimName> Builder<ConvexHull<N>> {
+    /// Compute the convex hull of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, N>])
+        -> Result<ConvexHull<N>, QhullError>
+        where DefaultAllocator: Allocator<f64, N>,
+    {
         unsafe {
-            let num_points = slice.len();
-            let len = slice.len() * std::mem::size_of::<[f64; N]>();
-            let buf = libc::malloc(len);
-            libc::memcpy(buf, slice.as_ptr().cast(), len);
-            Self::init_shared(buf.cast(), num_points)
+            ConvexHull::try_init_shared(malloc_points(slice), slice.len(), &self.command())
         }
     }
 }
 
 
 impl<N: DimName> ConvexHull<N> {
+    /// Compute the convex hull of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_vectors` to handle the error instead.
     pub fn from_vectors(slice: &[OVector<f64, N>]) -> Self
         where DefaultAllocator: Allocator<f64, N>,
     {
-        unsafe {
-            let num_points = slice.len();
-            let len = slice.len() * std::mem::size_of::<OVector<f64, N>>();
-            let buf = libc::malloc(len);
-            libc::memcpy(buf, slice.as_ptr().cast(), len);
-            Self::init_shared(buf.cast(), num_points)
-        }
+        Self::try_from_vectors(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the convex hull of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_vectors(slice: &[OVector<f64, N>]) -> Result<Self, QhullError>
+        where DefaultAllocator: Allocator<f64, N>,
+    {
+        Self::builder().build_from_vectors(slice)
     }
 
-    unsafe fn init_shared(buf: *mut f64, len: usize) -> Self {
-        let qh = qhull_sys::rqh_malloc();
-        // TODO:
-        let stdin = qhull_sys::rqh_stdin();
-        let stdout = qhull_sys::rqh_stdout();
-        let stderr = qhull_sys::rqh_stderr();
-        qhull_sys::qh_init_A(qh, stdin, stdout, stderr, 0, ptr::null_mut());
-        *qhull_sys::rqh_get_NOerrexit(qh) = 0;
-        let mut cmd = *b"qhull   Qc Qx         Qt\0";
-        qhull_sys::qh_initflags(qh, cmd.as_mut_ptr().cast());
-        qhull_sys::qh_init_B(qh, buf, len as _, N::dim() as _, 1);
-        qhull_sys::qh_qhull(qh);
-        qhull_sys::qh_triangulate(qh);
-        ConvexHull {
-            qh: NonNull::new(qh).unwrap(),
+    /// Create a builder for computing a convex hull with custom qhull options.
+    ///
+    /// Unlike the other hull types, the raw hull doesn't require simplicial facets, so leaving out
+    /// `Qt` will give you the merged facets.
+    pub fn builder() -> Builder<Self> {
+        Builder::new("", CONVEX_HULL_OPTIONS)
+    }
+
+    unsafe fn try_init_shared(buf: *mut f64, len: usize, cmd: &str) -> Result<Self, QhullError> {
+        let qh = run_qhull(cmd, buf, len, N::dim())?;
+        Ok(ConvexHull {
+            qh,
             pd: PhantomData,
-        }
+        })
     }
 
     pub fn facets<'a>(&'a self) -> FacetListIterator<'a, N> {
@@ -90,33 +191,14 @@ impl<N: DimName> FromIterator<OVector<f64, N>> for ConvexHull<N>
         //       Or perhaps we could use a custom allocator to ensure we can safely hand ownership
         //       over to the qhull library
         let v: Vec<_> = iter.into_iter().collect();
-        unsafe {
-            let num_points = v.len();
-            let len = v.len() * std::mem::size_of::<OVector<f64, N>>();
-            let buf = libc::malloc(len);
-            libc::memcpy(buf, v.as_ptr().cast(), len);
-
-            // Drop `v` to free it's memory
-            let _ = v;
-            Self::init_shared(buf.cast(), num_points)
-        }
+        Self::from_vectors(&v)
     }
 }
 
 impl<N: DimName> Drop for ConvexHull<N> {
     fn drop(&mut self) {
         unsafe {
-            qhull_sys::qh_freeqhull(self.qh.as_ptr(), 0);
-
-            let mut tolong = 0;
-            let mut currlong = 0;
-            qhull_sys::qh_memfreeshort(
-                self.qh.as_ptr(),
-                ptr::addr_of_mut!(currlong),
-                ptr::addr_of_mut!(tolong)
-            );
-
-            libc::free(self.qh.as_ptr().cast());
+            free_qhull(self.qh);
         }
     }
 }
@@ -218,13 +300,28 @@ impl<'a, N: DimName> Facet<'a, N> {
         }
     }
 
+    /// The Voronoi vertex (the center of the circumsphere) of this facet.
+    ///
+    /// This is only available for the facets of a `Voronoi` diagram, and has one fewer dimension
+    /// than the facet itself.
+    pub fn center(&self) -> Option<&'a [f64]> {
+        unsafe {
+            let center_ptr = ptr::addr_of!((*self.ptr.as_ptr()).center).read();
+            if center_ptr.is_null() {
+                None
+            } else {
+                Some(std::slice::from_raw_parts(center_ptr, N::dim() - 1))
+            }
+        }
+    }
+
     pub fn plane_distance(&self, point: &OVector<f64, N>) -> f64
         where DefaultAllocator: Allocator<f64, N>,
     {
         let mut sum = unsafe { ptr::addr_of!((*self.ptr.as_ptr()).offset).read() };
         let normal_ptr = unsafe { ptr::addr_of!((*self.ptr.as_ptr()).normal).read() };
         for (i, f) in point.iter().enumerate() {
-            sum += unsafe { normal_ptr.offset(i as isize).read() } * f;
+            sum += unsafe { normal_ptr.add(i).read() } * f;
         }
         sum
     }
@@ -273,10 +370,7 @@ pub struct SetIter<'a, T> {
 
 impl<'a, T> Clone for SetIter<'a, T> {
     fn clone(&self) -> Self {
-        SetIter {
-            ptr: self.ptr.clone(),
-            pd: PhantomData,
-        }
+        *self
     }
 }
 
@@ -320,13 +414,11 @@ impl<'a, N: DimName> Iterator for FacetListIterator<'a, N> {
                     return None
                 }
                 let next_ptr = NonNull::new(ptr::addr_of!((*ptr.as_ptr()).next).read());
-                if next_ptr.is_none() {
-                    return None
-                }
+                next_ptr?;
                 self.ptr = next_ptr;
             }
             Some(Facet {
-                ptr: ptr,
+                ptr,
                 pd: PhantomData,
             })
         } else {
@@ -355,7 +447,7 @@ impl<'a, N: DimName> Iterator for VertexListIterator<'a, N> {
                 self.ptr = NonNull::new(ptr::addr_of!((*ptr.as_ptr()).next).read());
             }
             Some(Vertex {
-                ptr: ptr,
+                ptr,
                 pd: PhantomData,
             })
         } else {
@@ -385,48 +477,76 @@ impl<const N: usize> Delaunay<Const<N>>
           Const<N>: DimAdd<U1>,
           DimSum<Const<N>, U1>: DimName,
 {
+    /// Compute the Delaunay triangulation of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
     pub fn from_arrays(slice: &[[f64; N]]) -> Self {
+        Self::try_from_arrays(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the Delaunay triangulation of the points in `slice`, returning qhull's error on
+    /// failure.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Self::builder().build(slice)
+    }
+}
+
+impl<const N: usize> Builder<Delaunay<Const<N>>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          Const<N>: DimAdd<U1>,
+          DimSum<Const<N>, U1>: DimName,
+{
+    /// Compute the Delaunay triangulation of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<Delaunay<Const<N>>, QhullError> {
         unsafe {
-            let num_points = slice.len();
-            let len = slice.len() * std::mem::size_of::<[f64; N]>();
-            let buf = libc::malloc(len);
-            libc::memcpy(buf, slice.as_ptr().cast(), len);
-            Self::init_shared(buf.cast(), num_points)
+            Delaunay::try_init_shared(malloc_points(slice), slice.len(), &self.simplicial_command())
         }
     }
 }
 
-impl<N: DimName> Delaunay<N>
+impl<N: DimName> Builder<Delaunay<N>>
     where DefaultAllocator: Allocator<f64, N>,
           N: DimAdd<U1>,
           DimSum<N, U1>: DimName,
 {
-    pub fn from_vectors(slice: &[OVector<f64, N>]) -> Self {
+    /// Compute the Delaunay triangulation of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, N>])
+        -> Result<Delaunay<N>, QhullError>
+    {
         unsafe {
-            let num_points = slice.len();
-            let len = slice.len() * std::mem::size_of::<OVector<f64, N>>();
-            let buf = libc::malloc(len);
-            libc::memcpy(buf, slice.as_ptr().cast(), len);
-            Self::init_shared(buf.cast(), num_points)
+            Delaunay::try_init_shared(malloc_points(slice), slice.len(), &self.simplicial_command())
         }
     }
+}
+
+impl<N: DimName> Delaunay<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    /// Compute the Delaunay triangulation of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_vectors` to handle the error instead.
+    pub fn from_vectors(slice: &[OVector<f64, N>]) -> Self {
+        Self::try_from_vectors(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the Delaunay triangulation of the points in `slice`, returning qhull's error on
+    /// failure.
+    pub fn try_from_vectors(slice: &[OVector<f64, N>]) -> Result<Self, QhullError> {
+        Self::builder().build_from_vectors(slice)
+    }
 
-    unsafe fn init_shared(buf: *mut f64, len: usize) -> Self {
-        let qh = qhull_sys::rqh_malloc();
-        let stdin = qhull_sys::rqh_stdin();
-        let stdout = qhull_sys::rqh_stdout();
-        let stderr = qhull_sys::rqh_stderr();
-        qhull_sys::qh_init_A(qh, stdin, stdout, stderr, 0, ptr::null_mut());
-        *qhull_sys::rqh_get_NOerrexit(qh) = 0;
-        let mut cmd = *b"qhull d Qc Qx Qbb Q12 Qt Qz\0";
-        qhull_sys::qh_initflags(qh, cmd.as_mut_ptr().cast());
-        *qhull_sys::rqh_get_PROJECTdelaunay(qh) = 1;
-        qhull_sys::qh_init_B(qh, buf, len as _, N::dim() as _, 1);
-        qhull_sys::qh_qhull(qh);
-        qhull_sys::qh_triangulate(qh);
+    /// Create a builder for computing a Delaunay triangulation with custom qhull options.
+    ///
+    /// The `d` option is always included, as is `Qt` unless the input is joggled.
+    pub fn builder() -> Builder<Self> {
+        Builder::new("d", DELAUNAY_OPTIONS)
+    }
 
+    unsafe fn try_init_shared(buf: *mut f64, len: usize, cmd: &str) -> Result<Self, QhullError> {
         let cv = ConvexHull {
-            qh: NonNull::new(qh).unwrap(),
+            qh: run_qhull(cmd, buf, len, N::dim())?,
             pd: PhantomData,
         };
         let mut max_bounds = OVector::from_element(f64::MIN);
@@ -442,11 +562,11 @@ impl<N: DimName> Delaunay<N>
             }
         }
 
-        Delaunay {
+        Ok(Delaunay {
             cv,
             max_bounds,
             min_bounds,
-        }
+        })
     }
 
     pub fn simplices<'a>(&'a self) -> FacetListIterator<'a, N> {
@@ -521,7 +641,7 @@ impl<N: DimName> Delaunay<N>
                 pd: PhantomData,
             }
         } else {
-            self.full_simplices().filter(|s| !s.is_upper_delaunay()).next()?
+            self.full_simplices().find(|s| !s.is_upper_delaunay())?
         };
         let mut best_distance = simplex.plane_distance(&projected_point);
         let mut changed = true;
@@ -538,7 +658,6 @@ impl<N: DimName> Delaunay<N>
                     changed = true;
                     best_distance = neighbor_distance;
                     simplex = neighbor;
-                } else {
                 }
             }
         }
@@ -579,7 +698,6 @@ impl<N: DimName> Delaunay<N>
                 } else if !(coord <= 1.0 + tolerance) {
                     // Keep looking for a negative coordinate but note this isn't a match
                     inside = false;
-                } else {
                 }
 
             }
@@ -715,7 +833,7 @@ impl<N: DimName> Delaunay<N>
                 pd: PhantomData,
             }
         } else {
-            self.full_simplices().filter(|s| !s.is_upper_delaunay()).next()?
+            self.full_simplices().find(|s| !s.is_upper_delaunay())?
         };
         let mut best_distance = simplex.plane_distance(&projected_point);
         let mut changed = true;
@@ -733,7 +851,6 @@ impl<N: DimName> Delaunay<N>
                     changed = true;
                     best_distance = neighbor_distance;
                     simplex = neighbor;
-                } else {
                 }
             }
         }
@@ -950,7 +1067,7 @@ impl<N: DimName> BarycentricSolver<N>
         let mut mat = simplices.insert_row(N::dim(), 1.0);
         assert!(mat.try_inverse_mut());
         BarycentricSolver {
-            mat: mat,
+            mat,
         }
     }
 
@@ -982,6 +1099,421 @@ impl<N: DimName> BarycentricSolver<N>
 }
 
 
+/// A ridge of a Voronoi diagram, the boundary between the regions of two input points.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoronoiRidge {
+    /// The indices of the input points on either side of the ridge.
+    pub points: [usize; 2],
+    /// The indices of the Voronoi vertices of the ridge. `None` is the vertex at infinity.
+    pub vertices: Vec<Option<usize>>,
+}
+
+/// Nth dimensional Voronoi diagram.
+///
+/// This is computed from the Delaunay triangulation of the input points, so the facets and
+/// vertices are those of the triangulation. Each facet that isn't an upper Delaunay facet is a
+/// Voronoi vertex, located at `Facet::center`, and each vertex is an input point (a site).
+///
+/// This type is a type-safe wrapper around raw the qhull structures. As a result, many operations
+/// involve walking linked lists and aren't particularly effecient. Prefer to use the other
+/// `Voronoi` type if possible.
+#[derive(Debug)]
+pub struct Voronoi<N: DimName>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    cv: ConvexHull<DimSum<N, U1>>,
+    points: Box<[OVector<f64, N>]>,
+}
+
+impl<const N: usize> Voronoi<Const<N>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          Const<N>: DimAdd<U1>,
+          DimSum<Const<N>, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
+    pub fn from_arrays(slice: &[[f64; N]]) -> Self {
+        Self::try_from_arrays(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the Voronoi diagram of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Self::builder().build(slice)
+    }
+}
+
+impl<const N: usize> Builder<Voronoi<Const<N>>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          Const<N>: DimAdd<U1>,
+          DimSum<Const<N>, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<Voronoi<Const<N>>, QhullError> {
+        let points: Vec<_> = slice.iter()
+            .map(|p| OVector::<f64, Const<N>>::from_row_slice(p))
+            .collect();
+        self.build_from_vectors(&points)
+    }
+}
+
+impl<N: DimName> Builder<Voronoi<N>>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, N>])
+        -> Result<Voronoi<N>, QhullError>
+    {
+        let qh = unsafe {
+            run_qhull(&self.command(), malloc_points(slice), slice.len(), N::dim())?
+        };
+        Ok(Voronoi {
+            cv: ConvexHull {
+                qh,
+                pd: PhantomData,
+            },
+            points: slice.into(),
+        })
+    }
+}
+
+impl<N: DimName> Voronoi<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_vectors` to handle the error instead.
+    pub fn from_vectors(slice: &[OVector<f64, N>]) -> Self {
+        Self::try_from_vectors(slice).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the Voronoi diagram of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_vectors(slice: &[OVector<f64, N>]) -> Result<Self, QhullError> {
+        Self::builder().build_from_vectors(slice)
+    }
+
+    /// Create a builder for computing a Voronoi diagram with custom qhull options.
+    ///
+    /// The `v` option is always included. Like SciPy, the default options are `Qbb Qc Qz`, plus
+    /// `Qx` for inputs with more than 4 dimensions.
+    pub fn builder() -> Builder<Self> {
+        let builder = Builder::new("v", VORONOI_OPTIONS);
+        if N::dim() > 4 {
+            builder.option(crate::Qhull::Qx)
+        } else {
+            builder
+        }
+    }
+
+    /// The input points.
+    pub fn points(&self) -> &[OVector<f64, N>] {
+        &self.points
+    }
+
+    /// The facets of the underlying Delaunay triangulation.
+    pub fn facets<'a>(&'a self) -> FacetListIterator<'a, DimSum<N, U1>> {
+        self.cv.facets()
+    }
+
+    /// The vertices of the underlying Delaunay triangulation. Each one is an input point.
+    pub fn vertices<'a>(&'a self) -> VertexListIterator<'a, DimSum<N, U1>> {
+        self.cv.vertices()
+    }
+
+    /// The index of the Voronoi vertex for `facet`, or `None` if the facet is an upper Delaunay
+    /// facet (which doesn't have a Voronoi vertex).
+    ///
+    /// Voronoi vertices are numbered in the order of `facets`, skipping upper Delaunay facets.
+    pub fn voronoi_vertex_index(&self, facet: &Facet<'_, DimSum<N, U1>>) -> Option<usize> {
+        if facet.is_upper_delaunay() {
+            return None
+        }
+        // qh_eachvoronoi_all numbers the facets this exact way, which is also what `ridges` needs.
+        self.facets()
+            .filter(|f| !f.is_upper_delaunay())
+            .position(|f| f == *facet)
+    }
+
+    /// The index of the input point for `vertex`.
+    ///
+    /// Returns `None` for vertices that aren't input points, such as the point at infinity added
+    /// by the `Qz` option.
+    pub fn point_index(&self, vertex: &Vertex<'_, DimSum<N, U1>>) -> Option<usize> {
+        let id = unsafe {
+            let point = ptr::addr_of!((*vertex.ptr.as_ptr()).point).read();
+            qhull_sys::qh_pointid(self.cv.qh.as_ptr(), point)
+        };
+        usize::try_from(id).ok().filter(|id| *id < self.points.len())
+    }
+
+    /// The facets around `vertex`, ordered so that the corresponding Voronoi vertices trace out
+    /// the boundary of the vertex's Voronoi region (for 2D diagrams). In other dimensions the
+    /// facets are in qhull's order.
+    pub fn ordered_neighbors<'a>(&'a self, vertex: &Vertex<'a, DimSum<N, U1>>)
+        -> Vec<Facet<'a, DimSum<N, U1>>>
+    {
+        if N::dim() == 2 {
+            order_by_adjacency(vertex)
+        } else {
+            vertex.neighbors().collect()
+        }
+    }
+
+    /// Compute all of the ridges of the Voronoi diagram.
+    ///
+    /// For 3D diagrams, the vertices of each ridge are in order around the ridge.
+    pub fn ridges(&self) -> Vec<VoronoiRidge> {
+        unsafe extern "C" fn visit_ridge(
+            qh: *mut qhT,
+            fp: *mut libc::FILE,
+            vertex: *mut qhull_sys::vertexT,
+            vertex_a: *mut qhull_sys::vertexT,
+            centers: *mut qhull_sys::setT,
+            _unbounded: std::os::raw::c_uint,
+        ) {
+            // qhull hands `fp` straight back to us, so it is used to smuggle the output through.
+            let state = &mut *(fp as *mut (Vec<VoronoiRidge>, usize));
+            let num_points = state.1;
+            let point_index = |v: *mut qhull_sys::vertexT| {
+                let id = qhull_sys::qh_pointid(qh, ptr::addr_of!((*v).point).read());
+                usize::try_from(id).ok().filter(|id| *id < num_points)
+            };
+            let (Some(a), Some(b)) = (point_index(vertex), point_index(vertex_a)) else {
+                return
+            };
+            let mut elements = NonNull::new_unchecked(ptr::addr_of_mut!((*centers).e).cast());
+            let mut vertices = vec![];
+            while let Some(facet) = next_set_element(&mut elements) {
+                let facet: NonNull<qhull_sys::facetT> = facet.cast();
+                // Voronoi vertex 0 is the vertex at infinity
+                let visit_id = ptr::addr_of!((*facet.as_ptr()).visitid).read() as usize;
+                vertices.push(visit_id.checked_sub(1));
+            }
+            state.0.push(VoronoiRidge {
+                points: [a, b],
+                vertices,
+            });
+        }
+
+        let mut state = (Vec::new(), self.points.len());
+        unsafe {
+            qhull_sys::qh_eachvoronoi_all(
+                self.cv.qh.as_ptr(),
+                ptr::addr_of_mut!(state).cast(),
+                Some(visit_ridge),
+                0,
+                qhull_sys::qh_RIDGE_qh_RIDGEall,
+                1,
+            );
+        }
+        state.0
+    }
+}
+
+/// Nth dimensional halfspace intersection.
+///
+/// Each halfspace is a normal and an offset, and contains the points `x` where
+/// `normal.dot(x) + offset <= 0`. This is the same convention qhull uses for the facets of a
+/// convex hull, so `Facet::normal` and `Facet::offset` can be used directly.
+///
+/// qhull computes the intersection as the convex hull of the duals of the halfspaces about the
+/// interior point, so the facets and vertices are those of the dual hull. Each facet is a vertex
+/// of the intersection, located at `intersection_point`, and each vertex is a non-redundant
+/// halfspace.
+///
+/// This type is a type-safe wrapper around raw the qhull structures. As a result, many operations
+/// involve walking linked lists and aren't particularly effecient. Prefer to use the other
+/// `HalfspaceIntersection` type if possible.
+#[derive(Debug)]
+pub struct HalfspaceIntersection<N: DimName>
+    where DefaultAllocator: Allocator<f64, N>,
+{
+    cv: ConvexHull<N>,
+    halfspaces: Box<[(OVector<f64, N>, f64)]>,
+    interior_point: OVector<f64, N>,
+}
+
+impl<const N: usize> HalfspaceIntersection<Const<N>> {
+    /// Compute the intersection of `halfspaces`. `interior_point` must be clearly inside all of
+    /// them.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
+    pub fn from_arrays(halfspaces: &[([f64; N], f64)], interior_point: [f64; N]) -> Self {
+        Self::try_from_arrays(halfspaces, interior_point).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the intersection of `halfspaces`, returning qhull's error on failure.
+    pub fn try_from_arrays(halfspaces: &[([f64; N], f64)], interior_point: [f64; N])
+        -> Result<Self, QhullError>
+    {
+        Self::builder().build(halfspaces, interior_point)
+    }
+}
+
+impl<const N: usize> Builder<HalfspaceIntersection<Const<N>>> {
+    /// Compute the intersection of `halfspaces` using these options.
+    pub fn build(&self, halfspaces: &[([f64; N], f64)], interior_point: [f64; N])
+        -> Result<HalfspaceIntersection<Const<N>>, QhullError>
+    {
+        let halfspaces: Vec<_> = halfspaces.iter()
+            .map(|(normal, offset)| (OVector::<f64, Const<N>>::from(*normal), *offset))
+            .collect();
+        self.build_from_vectors(&halfspaces, &interior_point.into())
+    }
+}
+
+impl<N: DimName> Builder<HalfspaceIntersection<N>>
+    where DefaultAllocator: Allocator<f64, N>,
+{
+    /// Compute the intersection of `halfspaces` using these options.
+    pub fn build_from_vectors(
+        &self,
+        halfspaces: &[(OVector<f64, N>, f64)],
+        interior_point: &OVector<f64, N>,
+    ) -> Result<HalfspaceIntersection<N>, QhullError> {
+        let coords: Vec<f64> = halfspaces.iter()
+            .flat_map(|(normal, offset)| normal.iter().copied().chain(iter::once(*offset)))
+            .collect();
+        let mut feasible: Vec<f64> = interior_point.iter().copied().collect();
+        let qh = unsafe {
+            run_qhull_feasible(
+                &self.command(),
+                malloc_points(&coords),
+                halfspaces.len(),
+                N::dim() + 1,
+                feasible.as_mut_ptr(),
+            )?
+        };
+        Ok(HalfspaceIntersection {
+            cv: ConvexHull {
+                qh,
+                pd: PhantomData,
+            },
+            halfspaces: halfspaces.into(),
+            interior_point: interior_point.clone(),
+        })
+    }
+}
+
+impl<N: DimName> HalfspaceIntersection<N>
+    where DefaultAllocator: Allocator<f64, N>,
+{
+    /// Compute the intersection of `halfspaces`. `interior_point` must be clearly inside all of
+    /// them.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_vectors` to handle the error instead.
+    pub fn from_vectors(halfspaces: &[(OVector<f64, N>, f64)], interior_point: &OVector<f64, N>)
+        -> Self
+    {
+        Self::try_from_vectors(halfspaces, interior_point).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Compute the intersection of `halfspaces`, returning qhull's error on failure.
+    pub fn try_from_vectors(
+        halfspaces: &[(OVector<f64, N>, f64)],
+        interior_point: &OVector<f64, N>,
+    ) -> Result<Self, QhullError> {
+        Self::builder().build_from_vectors(halfspaces, interior_point)
+    }
+
+    /// Create a builder for computing a halfspace intersection with custom qhull options.
+    ///
+    /// The `H` option is always included. Like SciPy, there are no other default options except
+    /// for `Qx` for inputs with more than 4 dimensions.
+    pub fn builder() -> Builder<Self> {
+        let builder = Builder::new("H", HALFSPACE_OPTIONS);
+        if N::dim() > 4 {
+            builder.option(crate::Qhull::Qx)
+        } else {
+            builder
+        }
+    }
+
+    /// The input halfspaces.
+    pub fn halfspaces(&self) -> &[(OVector<f64, N>, f64)] {
+        &self.halfspaces
+    }
+
+    /// The interior point the intersection was computed about.
+    pub fn interior_point(&self) -> &OVector<f64, N> {
+        &self.interior_point
+    }
+
+    /// The facets of the dual hull. Each one is a vertex of the intersection.
+    pub fn facets<'a>(&'a self) -> FacetListIterator<'a, N> {
+        self.cv.facets()
+    }
+
+    /// The vertices of the dual hull. Each one is a non-redundant halfspace.
+    pub fn vertices<'a>(&'a self) -> VertexListIterator<'a, N> {
+        self.cv.vertices()
+    }
+
+    /// The index of the input halfspace for `vertex`.
+    pub fn halfspace_index(&self, vertex: &Vertex<'_, N>) -> Option<usize> {
+        let id = unsafe {
+            let point = ptr::addr_of!((*vertex.ptr.as_ptr()).point).read();
+            qhull_sys::qh_pointid(self.cv.qh.as_ptr(), point)
+        };
+        usize::try_from(id).ok().filter(|id| *id < self.halfspaces.len())
+    }
+
+    /// The vertex of the intersection corresponding to `facet`.
+    ///
+    /// Returns `None` if the vertex is at infinity, which happens when the intersection is
+    /// unbounded.
+    pub fn intersection_point(&self, facet: &Facet<'_, N>) -> Option<OVector<f64, N>> {
+        // qhull's own output treats facets that don't clearly face away from the origin as being
+        // at infinity.
+        let min_denom = unsafe { qhull_sys::rqh_get_MINdenom(self.cv.qh.as_ptr()) };
+        let offset = facet.offset();
+        if offset >= -min_denom {
+            return None
+        }
+        let normal = OVector::<f64, N>::from_row_slice(&facet.normal()[..N::dim()]);
+        Some(normal / -offset + &self.interior_point)
+    }
+
+    /// The facets around `vertex`, ordered so that the corresponding intersection vertices trace
+    /// out the boundary of the halfspace's facet (for 3D intersections). In other dimensions the
+    /// facets are in qhull's order.
+    pub fn ordered_neighbors<'a>(&'a self, vertex: &Vertex<'a, N>) -> Vec<Facet<'a, N>> {
+        if N::dim() == 3 {
+            order_by_adjacency(vertex)
+        } else {
+            vertex.neighbors().collect()
+        }
+    }
+}
+
+/// The facets around a vertex of a 3D hull, ordered so that each facet is adjacent to the one
+/// before it. This is the order `qh_order_vertexneighbors` produces, but the vertex's neighbor set
+/// is left alone so iterators over it stay valid.
+fn order_by_adjacency<'a, N: DimName>(vertex: &Vertex<'a, N>) -> Vec<Facet<'a, N>> {
+    let mut remaining: Vec<_> = vertex.neighbors().collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    let Some(mut facet) = remaining.pop() else {
+        return ordered
+    };
+    ordered.push(facet);
+    while !remaining.is_empty() {
+        let Some(i) = remaining.iter().position(|f| facet.neighbors().any(|n| n == *f)) else {
+            // Not a proper 3D hull, so there is no order to find
+            ordered.append(&mut remaining);
+            break
+        };
+        facet = remaining.swap_remove(i);
+        ordered.push(facet);
+    }
+    ordered
+}
+
 #[test]
 fn test_cv_construction() {
     let points = [
@@ -1228,9 +1760,7 @@ fn test_delaunay_4d_find_simplex() {
         Some(&mut barycentric_coords),
         None,
     ).unwrap();
-    // panic!("{:#?}", solver_cache.0[simplex.id() as usize]);
     let mut points = simplex.vertices().map(|v| v.point()).collect::<Vec<_>>();
-    panic!("{:#?}", points);
     points.sort_by(|a, b| a.partial_cmp(b).unwrap());
     assert_eq!(points, [
         [3.0, 37.0, 3.0, 34.0],
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use nalgebra::OVector;
+use nalgebra::base::allocator::Allocator;
+use nalgebra::{Const, DefaultAllocator, DimAdd, DimName, DimSum, U1};
+
+use crate::{Builder, QhullError, RawVoronoi};
+
+/// N dimensional Voronoi diagram.
+///
+/// This type stores the data from qhull in a struct-of-arrays format modeled after
+/// `scipy.spatial.Voronoi`. Where SciPy uses `-1` to refer to the Voronoi vertex at infinity, this
+/// type uses `None`.
+#[derive(Clone, Debug)]
+pub struct Voronoi<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName,
+{
+    points: Box<[OVector<f64, N>]>,
+    vertices: Box<[OVector<f64, N>]>,
+    ridge_points: Box<[[usize; 2]]>,
+    ridge_vertices: Box<[Box<[Option<usize>]>]>,
+    regions: Box<[Box<[Option<usize>]>]>,
+    point_region: Box<[Option<usize>]>,
+}
+
+impl<N> Voronoi<N>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName + DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    /// Constructs a `Voronoi` from the raw qhull wrapper type.
+    ///
+    /// This is not the recommended way to construct an instance of this type. You probably want to
+    /// use the `from_arrays` method.
+    pub fn from_qhull_raw(voronoi: RawVoronoi<N>) -> Self {
+        // Every facet of the Delaunay triangulation that isn't an upper Delaunay facet is a Voronoi
+        // vertex. These are numbered in the same order that `RawVoronoi::ridges` uses.
+        let mut vertices = vec![];
+        let mut vertex_id_map = HashMap::<u64, usize>::new();
+        for facet in voronoi.facets() {
+            if facet.is_upper_delaunay() {
+                continue
+            }
+            let center = facet.center()
+                .expect("qhull computes the center of every lower Delaunay facet");
+            vertex_id_map.insert(facet.id(), vertices.len());
+            vertices.push(OVector::<f64, N>::from_row_slice(center));
+        }
+
+        let (ridge_points, ridge_vertices) = voronoi.ridges()
+            .into_iter()
+            .map(|ridge| (ridge.points, ridge.vertices.into_boxed_slice()))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+
+        let mut regions = vec![];
+        let mut point_region = vec![None; voronoi.points().len()];
+        for vertex in voronoi.vertices() {
+            let Some(point) = voronoi.point_index(&vertex) else {
+                continue
+            };
+            let mut region = vec![];
+            let mut has_infinity = false;
+            for facet in voronoi.ordered_neighbors(&vertex) {
+                if facet.is_upper_delaunay() {
+                    // The vertex at infinity only needs to be listed once
+                    if !has_infinity {
+                        has_infinity = true;
+                        region.push(None);
+                    }
+                } else {
+                    region.push(Some(vertex_id_map[&facet.id()]));
+                }
+            }
+            point_region[point] = Some(regions.len());
+            regions.push(region.into_boxed_slice());
+        }
+
+        Voronoi {
+            points: voronoi.points().into(),
+            vertices: vertices.into_boxed_slice(),
+            ridge_points: ridge_points.into_boxed_slice(),
+            ridge_vertices: ridge_vertices.into_boxed_slice(),
+            regions: regions.into_boxed_slice(),
+            point_region: point_region.into_boxed_slice(),
+        }
+    }
+
+    /// Compute the Voronoi diagram of the points in `slice`, returning qhull's error on failure.
+    pub fn try_from_vectors(slice: &[OVector<f64, N>]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawVoronoi::try_from_vectors(slice)?))
+    }
+
+    /// Create a builder for computing a Voronoi diagram with custom qhull options.
+    pub fn builder() -> Builder<Self> {
+        RawVoronoi::<N>::builder().cast()
+    }
+
+    /// The input points.
+    pub fn points(&self) -> &[OVector<f64, N>] {
+        &self.points
+    }
+
+    /// The Voronoi vertices.
+    pub fn vertices(&self) -> &[OVector<f64, N>] {
+        &self.vertices
+    }
+
+    /// The pairs of input points on either side of each Voronoi ridge.
+    pub fn ridge_points(&self) -> &[[usize; 2]] {
+        &self.ridge_points
+    }
+
+    /// The indices of the Voronoi vertices that make up each ridge. The ridges are in the same
+    /// order as `ridge_points`.
+    pub fn ridge_vertices(&self) -> &[Box<[Option<usize>]>] {
+        &self.ridge_vertices
+    }
+
+    /// The indices of the Voronoi vertices that make up each Voronoi region.
+    ///
+    /// For 2D and 3D diagrams, the vertices are in order around the region. A region containing
+    /// `None` is unbounded.
+    pub fn regions(&self) -> &[Box<[Option<usize>]>] {
+        &self.regions
+    }
+
+    /// The index into `regions` of each input point's region.
+    ///
+    /// This is `None` for points that don't have a region of their own, such as duplicate points.
+    pub fn point_region(&self) -> &[Option<usize>] {
+        &self.point_region
+    }
+
+    /// The Voronoi region of the input point at `index`.
+    pub fn region_for_point(&self, index: usize) -> Option<&[Option<usize>]> {
+        let region = (*self.point_region.get(index)?)?;
+        Some(&self.regions[region])
+    }
+}
+
+impl<const N: usize> Voronoi<Const<N>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          Const<N>: DimAdd<U1>,
+          DimSum<Const<N>, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice`.
+    ///
+    /// Panics if qhull reports an error. Use `try_from_arrays` to handle the error instead.
+    pub fn from_arrays(slice: &[[f64; N]]) -> Self {
+        Self::from_qhull_raw(RawVoronoi::from_arrays(slice))
+    }
+
+    /// Like `from_arrays`, but returns qhull's error instead of panicking.
+    pub fn try_from_arrays(slice: &[[f64; N]]) -> Result<Self, QhullError> {
+        Ok(Self::from_qhull_raw(RawVoronoi::try_from_arrays(slice)?))
+    }
+}
+
+impl<const N: usize> Builder<Voronoi<Const<N>>>
+    where DefaultAllocator: Allocator<f64, Const<N>>,
+          Const<N>: DimAdd<U1>,
+          DimSum<Const<N>, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice` using these options.
+    pub fn build(&self, slice: &[[f64; N]]) -> Result<Voronoi<Const<N>>, QhullError> {
+        let raw = self.cast::<RawVoronoi<Const<N>>>().build(slice)?;
+        Ok(Voronoi::from_qhull_raw(raw))
+    }
+}
+
+impl<N> Builder<Voronoi<N>>
+    where DefaultAllocator: Allocator<f64, N>,
+          N: DimName + DimAdd<U1>,
+          DimSum<N, U1>: DimName,
+{
+    /// Compute the Voronoi diagram of the points in `slice` using these options.
+    pub fn build_from_vectors(&self, slice: &[OVector<f64, N>])
+        -> Result<Voronoi<N>, QhullError>
+    {
+        let raw = self.cast::<RawVoronoi<N>>().build_from_vectors(slice)?;
+        Ok(Voronoi::from_qhull_raw(raw))
+    }
+}
+
+
+#[test]
+fn test_voronoi_grid() {
+    // The same example as in the SciPy documentation
+    let points = [
+        [0.0, 0.0], [0.0, 1.0], [0.0, 2.0],
+        [1.0, 0.0], [1.0, 1.0], [1.0, 2.0],
+        [2.0, 0.0], [2.0, 1.0], [2.0, 2.0],
+    ];
+    let voronoi = Voronoi::from_arrays(&points[..]);
+
+    let mut vertices = voronoi.vertices().iter()
+        .map(|v| [(v[0] * 2.0).round() / 2.0, (v[1] * 2.0).round() / 2.0])
+        .collect::<Vec<_>>();
+    vertices.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(vertices, [[0.5, 0.5], [0.5, 1.5], [1.5, 0.5], [1.5, 1.5]]);
+
+    assert_eq!(voronoi.ridge_points().len(), 12);
+    assert_eq!(voronoi.ridge_points().len(), voronoi.ridge_vertices().len());
+
+    // Only the center point has a bounded region, and it is surrounded by every vertex.
+    let center_region = voronoi.region_for_point(4).unwrap();
+    assert_eq!(center_region.len(), 4);
+    assert!(center_region.iter().all(|v| v.is_some()));
+    for i in [0, 1, 2, 3, 5, 6, 7, 8] {
+        assert!(voronoi.region_for_point(i).unwrap().contains(&None));
+    }
+}
@@ -43,11 +43,12 @@ fn main() {
         .flag("-Wno-unused-but-set-variable");
     if env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
         build.flag("-DWASM").flag("-flto");
-        build.flag(&format!("--sysroot={}", env::var("WASM_SYSROOT").unwrap()));
+        build.flag(format!("--sysroot={}", env::var("WASM_SYSROOT").unwrap()));
         println!("cargo:rerun-if-env-changed=WASM_SYSROOT");
     }
     for c_file in C_FILES {
         build.file(c_file);
+        println!("cargo:rerun-if-changed={}", c_file);
     }
     for header_file in HEADER_FILES {
         println!("cargo:rerun-if-changed={}", header_file);
@@ -1056,6 +1056,9 @@ extern "C" {
 extern "C" {
     pub fn rqh_get_last_newhigh(qh: *mut qhT) -> f64;
 }
+extern "C" {
+    pub fn rqh_get_MINdenom(qh: *mut qhT) -> f64;
+}
 extern "C" {
     pub fn rqh_stdin() -> *mut FILE;
 }
@@ -1065,3 +1068,14 @@ extern "C" {
 extern "C" {
     pub fn rqh_stderr() -> *mut FILE;
 }
+extern "C" {
+    pub fn rqh_run_qhull(
+        qh: *mut qhT,
+        cmd: *mut ::std::os::raw::c_char,
+        points: *mut f64,
+        numpoints: ::std::os::raw::c_int,
+        dim: ::std::os::raw::c_int,
+        feasible: *mut f64,
+        message: *mut *mut ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
@@ -1,3 +1,8 @@
+/* open_memstream is POSIX, so it isn't declared under -ansi without this. */
+#define _POSIX_C_SOURCE 200809L
+
+#include <string.h>
+
 #include "libqhull_r/geom_r.h"
 #include "libqhull_r/io_r.h"
 #include "libqhull_r/libqhull_r.h"
@@ -46,6 +51,10 @@ realT rqh_get_last_newhigh(qhT *qh) {
     return qh->last_newhigh;
 }
 
+realT rqh_get_MINdenom(qhT *qh) {
+    return qh->MINdenom;
+}
+
 FILE* rqh_stdin() {
     return stdin;
 }
@@ -57,3 +66,99 @@ FILE* rqh_stdout() {
 FILE* rqh_stderr() {
     return stderr;
 }
+
+/* Run qhull on `points` using the options in `cmd`, taking ownership of `points`.
+
+   Unlike calling qh_qhull directly, errors don't exit the process. Instead, qhull longjmps back
+   here (the same way qh_new_qhull does it) and the exit code is returned. On error, any text qhull
+   reported is written to a newly allocated string in `message`, which the caller must free.
+
+   wasm has no setjmp, so there qhull can't be stopped at an error. It carries on, which usually
+   ends the process at the next error. If it does get to the end, the error is still returned.
+   Joggled input (the `QJ` option) always fails there, since qhull's retry loop uses setjmp too.
+
+   For halfspace intersection (the `H` option), `points` are halfspaces, each a normal followed by
+   an offset, and `feasible` is a point of dimension `dim - 1` that is clearly inside all of them.
+   If `feasible` is NULL, the point given with the `H` option is used instead. `feasible` is
+   ignored for everything else.
+
+   Whether this succeeds or not, the caller still needs to free `qh` with qh_freeqhull.
+   */
+int rqh_run_qhull(qhT *qh, char *cmd, coordT *points, int numpoints, int dim, coordT *feasible,
+                  char **message) {
+    int exitcode;
+    size_t message_len = 0;
+    FILE *ferr = qh->ferr;
+    FILE *stream;
+    /* The points we own until qh_init_B takes them. This changes after setjmp, so it must be
+       volatile to still be valid after a longjmp. */
+    coordT * volatile owned = points;
+
+    *message = NULL;
+    stream = open_memstream(message, &message_len);
+    if (stream) {
+        qh->ferr = stream;
+        qh->qhmem.ferr = stream;
+    }
+#ifndef WASM
+    exitcode = setjmp(qh->errexit);
+#else
+    /* libqhull_r.h defines setjmp as -1 on wasm, so calling it would fail every run. Run qhull
+       straight through instead, the way qhull-rs did before this function existed. */
+    exitcode = 0;
+#endif
+    if (!exitcode) {
+        qh->NOerrexit = False;
+        qh_initflags(qh, cmd);
+        if (qh->DELAUNAY)
+            qh->PROJECTdelaunay = True;
+        if (qh->HALFspace) {
+            /* Same as qh_new_qhull: intersect the halfspaces by computing the convex hull of
+               their duals about the feasible point */
+            coordT *dual;
+            dim--;
+            if (feasible) {
+                if (!(qh->feasible_point = (pointT *)qh_malloc(dim * sizeof(coordT)))) {
+                    qh_fprintf(qh, qh->ferr, 6079,
+                               "qhull error: insufficient memory for the feasible point\n");
+                    qh_errexit(qh, qh_ERRmem, NULL, NULL);
+                }
+                memcpy(qh->feasible_point, feasible, dim * sizeof(coordT));
+            } else {
+                qh_setfeasible(qh, dim);
+            }
+            dual = qh_sethalfspace_all(qh, dim + 1, numpoints, owned, qh->feasible_point);
+            qh_free(owned);
+            owned = dual;
+        }
+        qh_init_B(qh, owned, numpoints, dim, True);
+        qh_qhull(qh);
+        if (qh->TRIangulate)
+            qh_triangulate(qh);
+        if (qh->VORONOI)
+            qh_setvoronoi_all(qh);
+        if (qh->HALFspace)
+            qh_vertexneighbors(qh);
+#ifdef WASM
+        /* qh_errexit sets NOerrexit where it would have jumped back, so that is how an error
+           shows up here. qhull carries on after it, but its results can't be used. */
+        if (qh->NOerrexit)
+            exitcode = qh_ERRqhull;
+#endif
+    } else if (!qh->first_point) {
+        /* qhull failed before it took ownership of the points */
+        qh_free(owned);
+    }
+    qh->NOerrexit = True;
+
+    qh->ferr = ferr;
+    qh->qhmem.ferr = ferr;
+    if (stream) {
+        fclose(stream);
+        if (!exitcode) {
+            free(*message);
+            *message = NULL;
+        }
+    }
+    return exitcode;
+}
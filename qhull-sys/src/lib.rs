@@ -10,6 +10,7 @@
 mod binding {
     #![allow(non_camel_case_types)]
     #![allow(non_snake_case)]
+    #![allow(clippy::all)]
     #![allow(non_upper_case_globals)]
 
     #[repr(C)]